@@ -0,0 +1,11 @@
+/* receipts of each stored block's transactions, when tail.receipts is on.
+   gas cost in wei is gas_used * effective_gas_price */
+CREATE TABLE IF NOT EXISTS receipts (
+  block_number Int4 NOT NULL,
+  transaction_index Int4 NOT NULL,
+  status BOOLEAN, /* null before byzantium */
+  gas_used Int8 NOT NULL,
+  effective_gas_price Int8,
+  contract_address VARCHAR(40),
+  PRIMARY KEY (block_number, transaction_index)
+);
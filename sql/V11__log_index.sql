@@ -0,0 +1,61 @@
+/* logIndex, unique within a block, makes every log and swap row
+   addressable so reprocessing a block upserts instead of appending */
+ALTER TABLE logs ADD COLUMN IF NOT EXISTS log_index Int4;
+ALTER TABLE swaps ADD COLUMN IF NOT EXISTS log_index Int4;
+
+/* rows stored before log_index: reruns of a block appended identical
+   copies. two identical logs in one transaction can not be told apart
+   from copies and are kept once. */
+DELETE FROM logs WHERE ctid IN (
+  SELECT ctid FROM (
+    SELECT ctid, row_number() OVER (
+      PARTITION BY block_number, transaction_index, address, data,
+        topic0, topic1, topic2, topic3, topic4, topic5
+      ORDER BY ctid) AS copy
+    FROM logs
+    WHERE log_index IS NULL) numbered
+  WHERE copy > 1);
+
+/* numbered in insertion order. that is the chain's logIndex when every
+   log of the block was stored, the default logs config */
+UPDATE logs SET log_index = numbered.log_index
+FROM (
+  SELECT ctid, row_number() OVER (
+    PARTITION BY block_number ORDER BY transaction_index, ctid) - 1 AS log_index
+  FROM logs) numbered
+WHERE logs.ctid = numbered.ctid AND logs.log_index IS NULL;
+
+ALTER TABLE logs ALTER COLUMN log_index SET NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS logs_block_number_log_index ON logs (block_number, log_index);
+
+DELETE FROM swaps WHERE ctid IN (
+  SELECT ctid FROM (
+    SELECT ctid, row_number() OVER (
+      PARTITION BY pool_contract_address, block_number, transaction_index,
+        in0, in1, out0, out1
+      ORDER BY ctid) AS copy
+    FROM swaps
+    WHERE log_index IS NULL) numbered
+  WHERE copy > 1);
+
+/* the nth swap of a pool in a transaction came from its nth Swap log */
+UPDATE swaps SET log_index = swap_logs.log_index
+FROM (
+  SELECT ctid, row_number() OVER (
+    PARTITION BY pool_contract_address, block_number, transaction_index ORDER BY ctid) AS n
+  FROM swaps) numbered,
+  (
+  SELECT address, block_number, transaction_index, log_index, row_number() OVER (
+    PARTITION BY address, block_number, transaction_index ORDER BY log_index) AS n
+  FROM logs
+  WHERE topic0 = 'd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822') swap_logs
+WHERE swaps.ctid = numbered.ctid
+  AND swaps.log_index IS NULL
+  AND swap_logs.address = swaps.pool_contract_address
+  AND swap_logs.block_number = swaps.block_number
+  AND swap_logs.transaction_index = swaps.transaction_index
+  AND swap_logs.n = numbered.n;
+
+/* swaps whose log was never stored keep a null log_index, which never
+   conflicts */
+CREATE UNIQUE INDEX IF NOT EXISTS swaps_block_number_log_index ON swaps (block_number, log_index);
@@ -0,0 +1,34 @@
+/* uniswap v2 Mint and Burn, keyed like swaps */
+CREATE TABLE IF NOT EXISTS liquidity_events (
+  pool_contract_address VARCHAR(40) NOT NULL,
+  block_number Int4 NOT NULL,
+  transaction_index Int4 NOT NULL,
+  log_index Int4 NOT NULL,
+  kind VARCHAR(4) NOT NULL, /* mint or burn */
+  sender VARCHAR(40) NOT NULL,
+  to_address VARCHAR(40), /* burn only */
+  amount0 DECIMAL,
+  amount1 DECIMAL,
+  PRIMARY KEY (block_number, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS liquidity_events_pool ON liquidity_events (pool_contract_address, block_number);
+CREATE INDEX IF NOT EXISTS liquidity_events_sender ON liquidity_events (sender);
+CREATE INDEX IF NOT EXISTS liquidity_events_to_address ON liquidity_events (to_address);
+
+/* pool (LP) token Transfers of pools in the pools table. mints come from
+   the zero address, burns go to the pool first */
+CREATE TABLE IF NOT EXISTS lp_transfers (
+  pool_contract_address VARCHAR(40) NOT NULL,
+  block_number Int4 NOT NULL,
+  transaction_index Int4 NOT NULL,
+  log_index Int4 NOT NULL,
+  from_address VARCHAR(40) NOT NULL,
+  to_address VARCHAR(40) NOT NULL,
+  value DECIMAL,
+  PRIMARY KEY (block_number, log_index)
+);
+
+CREATE INDEX IF NOT EXISTS lp_transfers_pool ON lp_transfers (pool_contract_address, block_number);
+CREATE INDEX IF NOT EXISTS lp_transfers_from_address ON lp_transfers (from_address);
+CREATE INDEX IF NOT EXISTS lp_transfers_to_address ON lp_transfers (to_address);
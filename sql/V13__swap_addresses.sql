@@ -0,0 +1,14 @@
+/* Swap's indexed sender (usually a router) and to (the recipient) */
+ALTER TABLE swaps ADD COLUMN IF NOT EXISTS sender VARCHAR(40);
+ALTER TABLE swaps ADD COLUMN IF NOT EXISTS to_address VARCHAR(40);
+
+/* swaps stored before this have them in their stored Swap log */
+UPDATE swaps SET sender = right(logs.topic1, 40), to_address = right(logs.topic2, 40)
+FROM logs
+WHERE swaps.sender IS NULL
+  AND logs.block_number = swaps.block_number
+  AND logs.log_index = swaps.log_index
+  AND logs.topic0 = 'd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822';
+
+CREATE INDEX IF NOT EXISTS swaps_sender ON swaps (sender, block_number);
+CREATE INDEX IF NOT EXISTS swaps_to_address ON swaps (to_address, block_number);
@@ -0,0 +1,5 @@
+/* position in the factory's allPairs. discover resumes after the highest
+   one stored. null for pools only seen through their Sync logs */
+ALTER TABLE pools ADD COLUMN IF NOT EXISTS uniswap_v2_index Int4;
+
+CREATE UNIQUE INDEX IF NOT EXISTS pools_uniswap_v2_index ON pools (uniswap_v2_index);
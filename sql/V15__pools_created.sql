@@ -0,0 +1,6 @@
+/* where the factory's PairCreated for the pool was seen. null for pools
+   stored by discover or from their first Sync */
+ALTER TABLE pools ADD COLUMN IF NOT EXISTS created_block_number Int4;
+ALTER TABLE pools ADD COLUMN IF NOT EXISTS created_transaction_index Int4;
+
+CREATE INDEX IF NOT EXISTS pools_created_block_number ON pools (created_block_number);
@@ -0,0 +1,18 @@
+/* decimal-adjusted spot prices from each stored reserves row. price0 is
+   token1 per whole token0, price1 token0 per whole token1. numeric
+   division keeps at least 16 significant digits, price::Price is exact.
+   null while a reserve is 0. */
+CREATE OR REPLACE VIEW prices AS
+SELECT
+  reserves.contract_address AS pool_contract_address,
+  reserves.block_number,
+  pools.token0,
+  pools.token1,
+  (reserves.y::numeric * power(10::numeric, coin0.decimals))
+    / NULLIF(reserves.x::numeric * power(10::numeric, coin1.decimals), 0) AS price0,
+  (reserves.x::numeric * power(10::numeric, coin1.decimals))
+    / NULLIF(reserves.y::numeric * power(10::numeric, coin0.decimals), 0) AS price1
+FROM reserves
+JOIN pools ON pools.contract_address = reserves.contract_address
+JOIN coins coin0 ON coin0.contract_address = pools.token0
+JOIN coins coin1 ON coin1.contract_address = pools.token1;
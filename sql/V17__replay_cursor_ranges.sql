@@ -0,0 +1,4 @@
+/* the --from/--to range a cursor was moving through. replay only resumes
+   a cursor of the same range. null for cursors from before, never resumed */
+ALTER TABLE replay_cursors ADD COLUMN IF NOT EXISTS from_block Int4;
+ALTER TABLE replay_cursors ADD COLUMN IF NOT EXISTS to_block Int4;
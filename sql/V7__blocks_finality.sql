@@ -0,0 +1,2 @@
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS safe BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE blocks ADD COLUMN IF NOT EXISTS finalized BOOLEAN NOT NULL DEFAULT false;
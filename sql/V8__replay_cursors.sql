@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS replay_cursors (
+  handler VARCHAR(32) PRIMARY KEY,
+  block_number Int4
+);
@@ -0,0 +1,15 @@
+/* transactions of each stored block. joins to logs and swaps on
+   block_number + transaction_index */
+CREATE TABLE IF NOT EXISTS transactions (
+  block_number Int4 NOT NULL,
+  transaction_index Int4 NOT NULL,
+  hash VARCHAR(64) NOT NULL,
+  from_address VARCHAR(40) NOT NULL,
+  to_address VARCHAR(40), /* null for contract creations */
+  input TEXT,
+  PRIMARY KEY (block_number, transaction_index)
+);
+
+CREATE INDEX IF NOT EXISTS transactions_hash ON transactions (hash);
+CREATE INDEX IF NOT EXISTS transactions_from_address ON transactions (from_address);
+CREATE INDEX IF NOT EXISTS transactions_to_address ON transactions (to_address);
@@ -1,18 +1,179 @@
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::OnceLock;
+use std::time::Duration;
 
 pub static CONFIG: OnceLock<Config> = OnceLock::new();
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub private_key: String,
+    #[serde(default)]
     pub geth_url: String,
+    // more endpoints to fail over between, tried healthiest first
+    #[serde(default)]
+    pub geth_urls: Vec<String>,
+    // newHeads subscriptions for tail --ws
+    #[serde(default)]
+    pub geth_ws_url: Option<String>,
     pub psql: String,
     pub etherscan_key: String,
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    #[serde(default)]
+    pub logs: LogsConfig,
+    #[serde(default)]
+    pub backfill: BackfillConfig,
+    #[serde(default)]
+    pub tail: TailConfig,
+    #[serde(default)]
+    pub handlers: HandlersConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+}
+
+// prometheus endpoint, under `metrics:` in config.yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    // address to serve /metrics on, eg 127.0.0.1:9898. off when missing.
+    pub listen: Option<String>,
+}
+
+// event handlers run on each block's logs, under `handlers:` in config.yaml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandlersConfig {
+    // handler names in the order they run. handlers not listed are off.
+    pub enabled: Vec<String>,
+    // handler name => contracts it runs for. handlers without an entry
+    // run on logs from every contract.
+    pub addresses: BTreeMap<String, Vec<String>>,
+}
+
+// lp_transfer is opt-in: it costs a pools lookup for every Transfer
+impl Default for HandlersConfig {
+    fn default() -> Self {
+        HandlersConfig {
+            enabled: vec![
+                "swap".to_string(),
+                "sync".to_string(),
+                "transfer".to_string(),
+                "mint".to_string(),
+                "burn".to_string(),
+                "pair_created".to_string(),
+            ],
+            addresses: BTreeMap::new(),
+        }
+    }
+}
+
+// tail command, under `tail:` in config.yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TailConfig {
+    // stay this many blocks behind the chain head. 0 ingests every block
+    // as soon as it is seen and relies on reorg rollback.
+    pub confirmations: u32,
+    // also fetch and store each block's transaction receipts
+    pub receipts: bool,
+}
+
+// backfill command, under `backfill:` in config.yaml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackfillConfig {
+    // threads fetching block ranges, overridden by --workers
+    pub workers: usize,
+    // blocks per eth_getLogs range
+    pub range: u32,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        BackfillConfig {
+            workers: 4,
+            range: 100,
+        }
+    }
+}
+
+// which logs to fetch and store, under `logs:` in config.yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    pub topics: LogTopics,
+    // only logs emitted by these contracts. empty for every contract.
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTopics {
+    // every log in the block, stored raw in logs
+    #[default]
+    All,
+    // only the topic0s of enabled handlers
+    Handled,
+}
+
+// geth::Client http settings, under `rpc:` in config.yaml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RpcConfig {
+    pub timeout_secs: u64,
+    pub retries: u32,
+    pub backoff_ms: u64,
+    pub backoff_max_ms: u64,
+    // save every request/response pair to this json fixture file
+    pub record: Option<String>,
+    // answer requests from a recorded fixture file instead of the network
+    pub replay: Option<String>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            timeout_secs: 12,
+            retries: 5,
+            backoff_ms: 500,
+            backoff_max_ms: 30_000,
+            record: None,
+            replay: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn geth_endpoints(&self) -> Vec<String> {
+        let mut urls = self.geth_urls.clone();
+        if !self.geth_url.is_empty() && !urls.contains(&self.geth_url) {
+            urls.insert(0, self.geth_url.clone());
+        }
+        urls
+    }
+}
+
+impl RpcConfig {
+    // exponential backoff with jitter: a random wait between half and all
+    // of backoff_ms * 2^(attempt-1), capped at backoff_max_ms
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let max_ms = self
+            .backoff_ms
+            .saturating_mul(1 << attempt.saturating_sub(1).min(20))
+            .min(self.backoff_max_ms);
+        Duration::from_millis(rand::thread_rng().gen_range(max_ms / 2..=max_ms))
+    }
 }
 
 pub fn load(filename: &str) -> Config {
     let yaml =
         std::fs::read_to_string(filename).unwrap_or_else(|err| panic!("{} {}", filename, err));
-    return serde_yaml::from_str(&yaml).unwrap_or_else(|err| panic!("{} {}", filename, err));
+    let config: Config =
+        serde_yaml::from_str(&yaml).unwrap_or_else(|err| panic!("{} {}", filename, err));
+    if config.geth_endpoints().is_empty() {
+        panic!("{}: set geth_url or geth_urls", filename);
+    }
+    config
 }
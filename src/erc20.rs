@@ -1,8 +1,8 @@
-use crate::geth::Client;
+use crate::geth::{CallResult, Client, Error, EthCall};
+use crate::multicall;
 use ethabi::token::Token;
 use ethabi::Contract;
 use ethereum_types::Address;
-use std::error::Error;
 use std::sync::OnceLock;
 
 // $ echo -n 'Transfer(address,address,uint256)' | sha3sum -a keccak256
@@ -11,44 +11,100 @@ pub const TOPIC_TRANSFER: &str =
 
 pub static ABI: OnceLock<Contract> = OnceLock::new();
 
+// name, symbol and decimals, each failing on its own
+pub type Metadata = (CallResult<String>, CallResult<String>, CallResult<u32>);
+
 #[derive(Debug, Default)]
 pub struct Erc20 {
     pub address: Address,
 }
 
-fn hex_to_ascii(str: &str) -> Result<String, Box<dyn Error>> {
-    let utf8_bytes_null = hex::decode(str.to_string())?;
+fn hex_to_ascii(str: &str) -> CallResult<String> {
+    let utf8_bytes_null = hex::decode(str).map_err(|e| Error::Decode(e.to_string()))?;
     let pos0 = utf8_bytes_null.iter().position(|&r| r == 0).unwrap_or(0);
     Ok(std::str::from_utf8(&utf8_bytes_null[0..pos0])
-        .unwrap()
+        .map_err(|e| Error::Decode(e.to_string()))?
         .to_owned())
 }
 
 impl Erc20 {
-    pub fn name(&self, geth: &Client) -> Result<String, Box<dyn std::error::Error>> {
-        match geth.eth_call(&self.address, &ABI.get().unwrap(), "name", &vec![], None) {
-            Ok(tokens) => Ok(tokens[0].to_string()),
-            Err(e) => hex_to_ascii(&e.to_string()), //fallback decode
-        }
+    // name, symbol and decimals in one multicall
+    pub fn metadata(&self, geth: &Client) -> CallResult<Metadata> {
+        Ok(Self::metadata_many(geth, &[self.address])?.remove(0))
+    }
+
+    // metadata for many tokens, still in one multicall
+    pub fn metadata_many(geth: &Client, addresses: &[Address]) -> CallResult<Vec<Metadata>> {
+        let calls = addresses
+            .iter()
+            .flat_map(|address| {
+                ["name", "symbol", "decimals"].map(|function_name| EthCall {
+                    to: *address,
+                    abi: ABI.get().unwrap(),
+                    function_name,
+                    function_params: vec![],
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = multicall::aggregate3(geth, &calls, None)?.into_iter();
+        Ok(addresses
+            .iter()
+            .map(|_| {
+                let name = decode_string(results.next().unwrap());
+                let symbol = decode_string(results.next().unwrap());
+                let decimals = decode_decimals(results.next().unwrap());
+                (name, symbol, decimals)
+            })
+            .collect())
     }
-    pub fn symbol(&self, geth: &Client) -> Result<String, Box<dyn std::error::Error>> {
-        match geth.eth_call(&self.address, &ABI.get().unwrap(), "symbol", &vec![], None) {
-            Ok(tokens) => Ok(tokens[0].to_string()),
-            Err(e) => hex_to_ascii(&e.to_string()), //fallback decode
-        }
+}
+
+fn decode_string(result: CallResult<Vec<Token>>) -> CallResult<String> {
+    match result {
+        Ok(tokens) => Ok(tokens[0].to_string()),
+        Err(e) => hex_to_ascii(&e.to_string()), //fallback decode
     }
-    pub fn decimals(&self, geth: &Client) -> Result<u32, Box<dyn std::error::Error>> {
-        let result = geth.eth_call(
-            &self.address,
-            &ABI.get().unwrap(),
-            "decimals",
-            &vec![],
-            None,
-        )?;
-        let Token::Uint(decimals) = result[0] else {
-            println!("{:?}", result[0]);
-            unreachable!()
+}
+
+fn decode_decimals(result: CallResult<Vec<Token>>) -> CallResult<u32> {
+    let result = result?;
+    let Token::Uint(decimals) = result[0] else {
+        return Err(Error::UnexpectedShape(format!(
+            "decimals => {:?}",
+            result[0]
+        )));
+    };
+    Ok(decimals.low_u32())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport;
+
+    #[test]
+    fn test_metadata_replay() {
+        ABI.get_or_init(|| Contract::load(std::fs::File::open("abi/ERC20.json").unwrap()).unwrap());
+        let geth = transport::fixture_client();
+        let weth = Erc20 {
+            address: Address::from_slice(
+                &hex::decode("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+            ),
         };
-        Ok(decimals.low_u32())
+        let (name, symbol, decimals) = weth.metadata(&geth).unwrap();
+        assert_eq!(name.unwrap(), "Wrapped Ether");
+        assert_eq!(symbol.unwrap(), "WETH");
+        assert_eq!(decimals.unwrap(), 18);
+    }
+
+    #[test]
+    fn test_decode_decimals_shape() {
+        let uint = Ok(vec![Token::Uint(18.into())]);
+        assert_eq!(decode_decimals(uint).unwrap(), 18);
+        let string = Ok(vec![Token::String("18".to_string())]);
+        assert!(matches!(
+            decode_decimals(string),
+            Err(Error::UnexpectedShape(_))
+        ));
     }
 }
@@ -0,0 +1,60 @@
+use crate::geth::{Client, InfuraBlock};
+use crate::sql::SqlQuery;
+use sql_query_builder as sql;
+use std::error::Error;
+
+// block tags post-merge nodes track, each with its column in blocks.
+// safe is unlikely to reorg, finalized cannot without slashing.
+pub const TAGS: [&str; 2] = ["safe", "finalized"];
+
+// flag stored blocks up to the chain's safe and finalized heads. a stored
+// hash that disagrees with the tagged block leaves the flags alone, the
+// reorg check has not caught up with that fork yet.
+pub fn mark(geth: &Client, db: &mut crate::sql::Client) -> Result<(), Box<dyn Error>> {
+    for tag in TAGS {
+        let Some(block) = geth.tagged_block(tag)? else {
+            continue; // pre-merge chain or node without the tag
+        };
+        if let Some(hash) = InfuraBlock::db_hash(db, block.number) {
+            if block.hash.strip_prefix("0x").unwrap_or(&block.hash) != hash {
+                log::warn!(
+                    "{} block #{} hash {} not the stored {}",
+                    tag,
+                    block.number,
+                    block.hash,
+                    hash
+                );
+                continue;
+            }
+        }
+        let rows = db.q(mark_sql(tag, block.number));
+        if !rows.is_empty() {
+            log::info!("{} #{}: marked {} blocks", tag, block.number, rows.len());
+        }
+    }
+    Ok(())
+}
+
+pub fn mark_sql(tag: &str, number: u32) -> SqlQuery {
+    let update = sql::Update::new()
+        .update("blocks")
+        .set(&format!("{} = true", tag))
+        .where_clause("number <= $1")
+        .where_clause(&format!("NOT {}", tag))
+        .returning("number");
+    (update.to_string(), vec![Box::new(number as i32)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_sql() {
+        let (sql, _) = mark_sql("finalized", 100);
+        assert_eq!(
+            sql,
+            "UPDATE blocks SET finalized = true WHERE number <= $1 AND NOT finalized RETURNING number"
+        );
+    }
+}
@@ -1,30 +1,179 @@
-use bs58;
+use crate::config::RpcConfig;
+use crate::transport::{self, Transport};
 use ethabi::token::Token;
 use ethabi::Contract;
 use ethereum_types::Address;
 use postgres::types::ToSql;
 use rand::Rng;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, time::Duration};
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionReceipt {
-    pub status: String,
+    #[serde(deserialize_with = "hexstr_to_u32")]
+    pub block_number: u32,
+    #[serde(deserialize_with = "hexstr_to_u32")]
+    pub transaction_index: u32,
+    pub transaction_hash: String,
+    // 0x1 success, 0x0 reverted. None before byzantium
+    pub status: Option<String>,
     pub cumulative_gas_used: String,
+    pub gas_used: String,
+    // None from nodes that predate london
+    pub effective_gas_price: Option<String>,
+    // the deployed contract, for contract creations
+    pub contract_address: Option<String>,
+}
+
+impl crate::sql::Ops for TransactionReceipt {
+    fn to_upsert_sql(&self) -> crate::sql::SqlQuery {
+        let hex_to_i64 =
+            |hex: &str| i64::from_str_radix(hex.strip_prefix("0x").unwrap(), 16).unwrap();
+        <dyn crate::Ops>::upsert_sql(
+            "receipts",
+            vec!["block_number", "transaction_index"],
+            vec![
+                "status",
+                "gas_used",
+                "effective_gas_price",
+                "contract_address",
+            ],
+            vec![
+                Box::new(self.block_number as i32),
+                Box::new(self.transaction_index as i32),
+                Box::new(self.status.as_ref().map(|status| hex_to_i64(status) == 1)),
+                Box::new(hex_to_i64(&self.gas_used)),
+                Box::new(self.effective_gas_price.as_deref().map(hex_to_i64)),
+                Box::new(
+                    self.contract_address
+                        .as_ref()
+                        .map(|address| address.strip_prefix("0x").unwrap().to_owned()),
+                ),
+            ],
+        )
+    }
+}
+
+// requests per json-rpc batch post. infura accepts more but large batches
+// are slow to fail and retry.
+pub const BATCH_SIZE: usize = 100;
+
+pub type CallResult<T> = Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // http or socket level failure, no json-rpc response to look at
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    // the node answered with a json-rpc error object
+    Rpc(ErrorDetailRpc),
+    // a response or contract output that could not be parsed
+    Decode(String),
+    // valid json-rpc, but not the result type the method returns
+    UnexpectedShape(String),
+}
+
+impl Error {
+    // worth asking again: the request never got an answer, or the node
+    // is rate limiting / temporarily unable to serve it
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(_) => true,
+            Error::Rpc(e) => matches!(e.code, -32000 | -32005 | -32603),
+            Error::Decode(_) | Error::UnexpectedShape(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport: {}", e),
+            Error::Rpc(e) => write!(f, "rpc: {}", e),
+            Error::Decode(e) => write!(f, "decode: {}", e),
+            Error::UnexpectedShape(e) => write!(f, "unexpected shape: {}", e),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
+// json-rpc error codes providers use for "slow down"
+const RATE_LIMIT_CODES: [i32; 2] = [-32005, 429];
+
+// json-rpc "method not found", eg eth_getBlockReceipts on older nodes
+const METHOD_NOT_FOUND: i32 = -32601;
+
+// blocks behind the highest observed eth_blockNumber before an endpoint
+// is only used when everything else has failed
+const MAX_LAG: u32 = 2;
+
 pub struct Client {
+    endpoints: Vec<Endpoint>,
+    rpc_config: RpcConfig,
+    transport: Box<dyn Transport>,
+}
+
+struct Endpoint {
     url: String,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Health {
+    pub block_number: Option<u32>,
+    pub failures: u32,
+    pub latency_ms: Option<u64>,
+}
+
+impl Endpoint {
+    fn health(&self) -> Health {
+        self.health.lock().unwrap().clone()
+    }
+
+    fn succeeded(&self, elapsed: Duration) {
+        let mut health = self.health.lock().unwrap();
+        let ms = elapsed.as_millis() as u64;
+        health.failures = 0;
+        health.latency_ms = Some(health.latency_ms.map_or(ms, |avg| (avg * 3 + ms) / 4));
+    }
+
+    fn failed(&self) {
+        self.health.lock().unwrap().failures += 1;
+    }
 }
 
 impl Client {
-    pub fn build(url: &str) -> Client {
+    pub fn build(urls: &[String]) -> Client {
         Client {
-            url: url.to_owned(),
+            endpoints: urls
+                .iter()
+                .map(|url| Endpoint {
+                    url: url.to_owned(),
+                    health: Mutex::new(Health::default()),
+                })
+                .collect(),
+            rpc_config: RpcConfig::default(),
+            transport: Box::new(transport::Http),
         }
     }
 
+    pub fn with_rpc_config(mut self, rpc_config: RpcConfig) -> Client {
+        self.rpc_config = rpc_config;
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Box<dyn Transport>) -> Client {
+        self.transport = transport;
+        self
+    }
+
     pub fn eth_call(
         &self,
         to: &Address,
@@ -32,122 +181,735 @@ impl Client {
         function_name: &str,
         function_params: &[Token],
         block_number: Option<u32>,
-    ) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
-        let function_call = abi.function(function_name).unwrap();
-        let function_input = function_call.encode_input(function_params).unwrap();
-        let to_hex = format!("0x{}", hex::encode(to));
-        let tx = tx_build(to_hex.clone(), function_input);
-        let params = (tx, infura_block_param(block_number));
-        let output = self.rpc_str("eth_call", ParamTypes::Infura(params))?;
-        let output_no_0x = output.strip_prefix("0x").unwrap();
-        let output_bytes = hex::decode(output_no_0x).unwrap();
-        match function_call.decode_output(&output_bytes) {
-            Err(err) => Err(format!(
-                "geth call {}({:?})@{} => Error decoding output {:?} {}",
-                function_name, function_params, to_hex, err, output_no_0x
-            )
-            .into()),
-            Ok(tokens) => Ok(tokens),
-        }
+    ) -> CallResult<Vec<Token>> {
+        let call = EthCall {
+            to: *to,
+            abi,
+            function_name,
+            function_params: function_params.to_vec(),
+        };
+        let output = self.rpc_str("eth_call", call.params(block_number)?)?;
+        call.decode(&output)
     }
 
-    pub fn rpc_str(
+    // many eth_calls in one http post. the outer error is for the post itself,
+    // each call gets its own result since single contract calls can revert.
+    pub fn eth_call_batch(
         &self,
-        method: &str,
-        params: ParamTypes,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let result = self.rpc(method, params);
-        match result {
-            Ok(rpc_result) => match rpc_result.part {
-                RpcResultTypes::Error(e) => Err(Box::try_from(e.error.message).unwrap()),
-                RpcResultTypes::Result(r) => {
-                    let str_ret = match r.result {
-                        ResultTypes::String(s) => s,
-                        _ => "-bad non-string response".to_string(),
-                    };
-                    Ok(str_ret)
+        calls: &[EthCall],
+        block_number: Option<u32>,
+    ) -> CallResult<Vec<CallResult<Vec<Token>>>> {
+        let requests = calls
+            .iter()
+            .map(|call| Ok(("eth_call", call.params(block_number)?)))
+            .collect::<CallResult<_>>()?;
+        let results = self.rpc_batch(requests)?;
+        Ok(calls
+            .iter()
+            .zip(results)
+            .map(|(call, result)| match result.part {
+                RpcResultTypes::Error(e) => Err(Error::Rpc(e.error)),
+                RpcResultTypes::Result(ResultRpc {
+                    result: ResultTypes::String(output),
+                }) => call.decode(&output),
+                RpcResultTypes::Result(r) => Err(Error::UnexpectedShape(format!(
+                    "geth call {} => {:?}",
+                    call.function_name, r
+                ))),
+            })
+            .collect())
+    }
+
+    pub fn rpc_str(&self, method: &str, params: ParamTypes) -> CallResult<String> {
+        string_result(method, self.rpc(method, params)?)
+    }
+
+    // asks every endpoint, remembering each answer for lag scoring, and
+    // returns the highest
+    pub fn last_block_number(&self) -> CallResult<u32> {
+        let retries = if self.endpoints.len() == 1 {
+            self.rpc_config.retries
+        } else {
+            0
+        };
+        let mut highest = None;
+        let mut last_error = None;
+        for (idx, endpoint) in self.endpoints.iter().enumerate() {
+            let result = self
+                .send(Some(idx), retries, "eth_blockNumber", ParamTypes::Empty)
+                .and_then(|result| string_result("eth_blockNumber", result))
+                .and_then(|hex| hex_to_u32(&hex));
+            match result {
+                Ok(number) => {
+                    endpoint.health.lock().unwrap().block_number = Some(number);
+                    highest = highest.max(Some(number));
                 }
-            },
-            Err(e) => Err(e),
+                Err(e) => last_error = Some(e),
+            }
         }
+        match (highest, last_error) {
+            (Some(number), _) => Ok(number),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(Error::UnexpectedShape("no rpc endpoints".to_string())),
+        }
+    }
+
+    pub fn endpoints_health(&self) -> Vec<(String, Health)> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| (endpoint.url.clone(), endpoint.health()))
+            .collect()
+    }
+
+    pub fn log_health(&self) {
+        let highest = self.highest_block_number();
+        for (url, health) in self.endpoints_health() {
+            log::info!(
+                "rpc endpoint {} block #{} lag {} failures {} latency {}",
+                url,
+                health
+                    .block_number
+                    .map_or("-".to_string(), |n| n.to_string()),
+                health
+                    .block_number
+                    .zip(highest)
+                    .map_or("-".to_string(), |(n, h)| (h - n).to_string()),
+                health.failures,
+                health
+                    .latency_ms
+                    .map_or("-".to_string(), |ms| format!("{}ms", ms)),
+            );
+        }
+    }
+
+    fn highest_block_number(&self) -> Option<u32> {
+        self.endpoints
+            .iter()
+            .filter_map(|endpoint| endpoint.health().block_number)
+            .max()
     }
 
-    pub fn last_block_number(&self) -> u32 {
-        let blk_num_str = self.rpc_str("eth_blockNumber", ParamTypes::Empty).unwrap();
-        u32::from_str_radix(&blk_num_str[2..], 16).unwrap()
+    // endpoint indexes, healthiest first: in sync, fewest recent failures,
+    // then fastest
+    fn ranked(&self) -> Vec<usize> {
+        let highest = self.highest_block_number().unwrap_or(0);
+        let mut ranked = (0..self.endpoints.len()).collect::<Vec<_>>();
+        ranked.sort_by_key(|idx| {
+            let health = self.endpoints[*idx].health();
+            let lagging = health.block_number.is_some_and(|n| n + MAX_LAG < highest);
+            (lagging, health.failures, health.latency_ms.unwrap_or(0))
+        });
+        ranked
     }
 
-    pub fn block(&self, block_number: u32) -> Result<InfuraBlock, Box<dyn std::error::Error>> {
+    pub fn block(&self, block_number: u32) -> CallResult<InfuraBlock> {
         let infura_block_number = infura_block_param(Some(block_number));
         let params = (infura_block_number, true);
+        block_result(self.rpc("eth_getBlockByNumber", ParamTypes::EthBlockByHash(params))?)
+    }
+
+    // number and hash of a tagged block: latest, safe or finalized.
+    // None when the node does not know the tag.
+    pub fn tagged_block(&self, tag: &str) -> CallResult<Option<InfuraBlockHeader>> {
+        let params = (tag.to_string(), false);
         match self
             .rpc("eth_getBlockByNumber", ParamTypes::EthBlockByHash(params))?
             .part
         {
-            RpcResultTypes::Result(result) => {
-                if let ResultTypes::Block(block) = result.result {
-                    Ok(block)
-                } else {
-                    Err(Box::from(format!(
-                        "geth.block: Unexpected result type {:?}",
-                        result
-                    )))
+            RpcResultTypes::Result(ResultRpc {
+                result: ResultTypes::BlockHeader(header),
+            }) => Ok(Some(header)),
+            // a block without transactions also parses as a full Block
+            RpcResultTypes::Result(ResultRpc {
+                result: ResultTypes::Block(block),
+            }) => Ok(Some(InfuraBlockHeader {
+                hash: block.hash,
+                number: block.number,
+            })),
+            RpcResultTypes::Result(ResultRpc {
+                result: ResultTypes::Null,
+            }) => Ok(None),
+            // -39001 unknown block, geth before the merge
+            RpcResultTypes::Error(e) if e.error.code == -39001 => Ok(None),
+            RpcResultTypes::Result(r) => Err(Error::UnexpectedShape(format!(
+                "eth_getBlockByNumber {} => {:?}",
+                tag, r
+            ))),
+            RpcResultTypes::Error(e) => Err(Error::Rpc(e.error)),
+        }
+    }
+
+    // from..=to in batched requests
+    pub fn blocks(&self, from: u32, to: u32) -> CallResult<Vec<InfuraBlock>> {
+        let mut blocks = vec![];
+        for chunk in (from..=to).collect::<Vec<_>>().chunks(BATCH_SIZE) {
+            let calls = chunk
+                .iter()
+                .map(|number| {
+                    let params = (infura_block_param(Some(*number)), true);
+                    ("eth_getBlockByNumber", ParamTypes::EthBlockByHash(params))
+                })
+                .collect();
+            for result in self.rpc_batch(calls)? {
+                blocks.push(block_result(result)?);
+            }
+        }
+        Ok(blocks)
+    }
+
+    // receipts for each block's transactions, in transaction order.
+    // eth_getBlockReceipts in batches, falling back to one
+    // eth_getTransactionReceipt per transaction on nodes without it.
+    pub fn receipts(&self, blocks: &[&InfuraBlock]) -> CallResult<Vec<Vec<TransactionReceipt>>> {
+        let mut receipts = vec![];
+        for chunk in blocks.chunks(BATCH_SIZE) {
+            let calls = chunk
+                .iter()
+                .map(|block| {
+                    let params = (infura_block_param(Some(block.number)),);
+                    ("eth_getBlockReceipts", ParamTypes::Single(params))
+                })
+                .collect();
+            for (block, result) in chunk.iter().zip(self.rpc_batch(calls)?) {
+                let block_receipts = match receipts_result(result) {
+                    Err(Error::Rpc(e)) if e.code == METHOD_NOT_FOUND => {
+                        self.transaction_receipts(block)?
+                    }
+                    result => result?,
+                };
+                if block_receipts.len() != block.transactions.len() {
+                    return Err(Error::UnexpectedShape(format!(
+                        "block #{} has {} transactions, {} receipts",
+                        block.number,
+                        block.transactions.len(),
+                        block_receipts.len()
+                    )));
                 }
+                receipts.push(block_receipts);
             }
-            RpcResultTypes::Error(err) => {
-                Err(Box::from(format!("geth.block: RPC Error {:?}", err)))
+        }
+        Ok(receipts)
+    }
+
+    fn transaction_receipts(&self, block: &InfuraBlock) -> CallResult<Vec<TransactionReceipt>> {
+        let mut receipts = vec![];
+        for chunk in block.transactions.chunks(BATCH_SIZE) {
+            let calls = chunk
+                .iter()
+                .map(|transaction| {
+                    let params = (transaction.hash.clone(),);
+                    ("eth_getTransactionReceipt", ParamTypes::Single(params))
+                })
+                .collect();
+            for result in self.rpc_batch(calls)? {
+                match result.part {
+                    RpcResultTypes::Result(ResultRpc {
+                        result: ResultTypes::TransactionReceipt(receipt),
+                    }) => receipts.push(receipt),
+                    RpcResultTypes::Result(r) => {
+                        return Err(Error::UnexpectedShape(format!(
+                            "eth_getTransactionReceipt => {:?}",
+                            r
+                        )))
+                    }
+                    RpcResultTypes::Error(e) => return Err(Error::Rpc(e.error)),
+                }
             }
         }
+        Ok(receipts)
     }
 
-    pub fn logs(&self, block_number: u32) -> Result<Vec<InfuraLog>, Box<dyn std::error::Error>> {
-        let infura_block_number = infura_block_param(Some(block_number));
-        let mut rpc_param = JsonRpcParam::new();
-        rpc_param.insert("fromBlock".to_owned(), infura_block_number.clone());
-        rpc_param.insert("toBlock".to_owned(), infura_block_number.clone());
+    pub fn logs(&self, block_number: u32, filter: &LogFilter) -> CallResult<Vec<InfuraLog>> {
+        self.get_logs(block_number, block_number, filter)
+    }
+
+    // from..=to, bisecting the range when the provider refuses to answer
+    // for that many logs at once
+    pub fn logs_range(&self, from: u32, to: u32, filter: &LogFilter) -> CallResult<Vec<InfuraLog>> {
+        match self.get_logs(from, to, filter) {
+            Err(Error::Rpc(e)) if from < to && is_logs_too_large(&e.message) => {
+                let mid = from + (to - from) / 2;
+                log::info!(
+                    "eth_getLogs #{}-#{} too large ({}). splitting at #{}",
+                    from,
+                    to,
+                    e,
+                    mid
+                );
+                let mut logs = self.logs_range(from, mid, filter)?;
+                logs.append(&mut self.logs_range(mid + 1, to, filter)?);
+                Ok(logs)
+            }
+            result => result,
+        }
+    }
+
+    fn get_logs(&self, from: u32, to: u32, filter: &LogFilter) -> CallResult<Vec<InfuraLog>> {
+        let rpc_param = filter.param(from, to);
         match self
-            .rpc("eth_getLogs", ParamTypes::Standard(vec![rpc_param]))
-            .unwrap()
+            .rpc("eth_getLogs", ParamTypes::Logs(vec![rpc_param]))?
             .part
         {
+            RpcResultTypes::Result(ResultRpc {
+                result: ResultTypes::Logs(logs),
+            }) => Ok(logs.into_iter().filter(|log| !log.removed).collect()),
             RpcResultTypes::Result(r) => {
-                if let ResultTypes::Logs(logs) = r.result {
-                    Ok(logs)
-                } else {
-                    panic!("Unexpected result type from eth_getLogs {:?}", r)
-                }
+                Err(Error::UnexpectedShape(format!("eth_getLogs => {:?}", r)))
             }
-            RpcResultTypes::Error(e) => return Err(Box::from(e.error.message)),
+            RpcResultTypes::Error(e) => Err(Error::Rpc(e.error)),
         }
     }
 
-    pub fn rpc(
+    pub fn rpc(&self, method: &str, params: ParamTypes) -> CallResult<JsonRpcResult> {
+        self.send(None, self.rpc_config.retries, method, params)
+    }
+
+    fn send(
         &self,
+        pinned: Option<usize>,
+        retries: u32,
         method: &str,
         params: ParamTypes,
-    ) -> Result<JsonRpcResult, Box<dyn std::error::Error>> {
+    ) -> CallResult<JsonRpcResult> {
         let params_str = format!("{:?}", params);
         let jrpc = JsonRpc {
             jsonrpc: "2.0".to_string(),
             id: gen_id(),
             method: method.to_string(),
-            params: params,
+            params,
+        };
+        let label = format!("{} {}", method, params_str);
+        self.post(
+            pinned,
+            retries,
+            method,
+            &label,
+            &jrpc,
+            |result: &JsonRpcResult| retry_for(&result.part),
+        )
+    }
+
+    // results are returned in request order, matched up by id
+    pub fn rpc_batch(&self, calls: Vec<(&str, ParamTypes)>) -> CallResult<Vec<JsonRpcResult>> {
+        if calls.is_empty() {
+            return Ok(vec![]);
+        }
+        let methods_str = calls
+            .iter()
+            .map(|(method, _)| *method)
+            .collect::<Vec<_>>()
+            .join(",");
+        // sorted so the metrics label is one per set of methods, not per order
+        let mut methods = calls.iter().map(|(method, _)| *method).collect::<Vec<_>>();
+        methods.sort_unstable();
+        methods.dedup();
+        let method = methods.join(",");
+        let jrpcs = calls
+            .into_iter()
+            .map(|(method, params)| JsonRpc {
+                jsonrpc: "2.0".to_string(),
+                id: gen_id(),
+                method: method.to_string(),
+                params,
+            })
+            .collect::<Vec<_>>();
+        let label = format!("batch[{}] {}", jrpcs.len(), methods_str);
+        let rpc_results = self.post(
+            None,
+            self.rpc_config.retries,
+            &method,
+            &label,
+            &jrpcs,
+            |results: &Vec<JsonRpcResult>| {
+                results.iter().map(|result| retry_for(&result.part)).fold(
+                    Retry::No,
+                    |worst, retry| {
+                        if retry > worst {
+                            retry
+                        } else {
+                            worst
+                        }
+                    },
+                )
+            },
+        )?;
+        let mut results_by_id = rpc_results
+            .into_iter()
+            .map(|r| (r.id.clone(), r))
+            .collect::<HashMap<_, _>>();
+        jrpcs
+            .iter()
+            .map(|jrpc| {
+                results_by_id.remove(&jrpc.id).ok_or_else(|| {
+                    Error::UnexpectedShape(format!(
+                        "geth batch: no response for {} id {}",
+                        jrpc.method, jrpc.id
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    // one http post with retries. transport failures, http 429/5xx,
+    // undecodable responses and rate limited or transient json-rpc errors
+    // fail over to the next healthiest endpoint. once every endpoint has
+    // failed, the round is retried with exponential backoff and jitter, or
+    // after the wait the provider asked for. other json-rpc errors are asked
+    // of every endpoint once, without backoff. when retries run out the last
+    // json-rpc error response is returned for the caller to report. pinned
+    // sends go to that one endpoint only.
+    fn post<T, R, F>(
+        &self,
+        pinned: Option<usize>,
+        retries: u32,
+        method: &str,
+        label: &str,
+        body: &T,
+        rate_limited: F,
+    ) -> CallResult<R>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+        F: Fn(&R) -> Retry,
+    {
+        if self.endpoints.is_empty() {
+            return Err(Error::Transport(
+                format!("{}: no rpc endpoints", label).into(),
+            ));
+        }
+        let body_str = serde_json::to_string(body)
+            .map_err(|e| Error::Decode(format!("{} request: {}", label, e)))?;
+        let mut attempt = 0;
+        let mut tried = vec![];
+        loop {
+            let idx = pinned.unwrap_or_else(|| {
+                let ranked = self.ranked();
+                ranked
+                    .iter()
+                    .find(|idx| !tried.contains(*idx))
+                    .copied()
+                    .unwrap_or(ranked[0])
+            });
+            let endpoint = &self.endpoints[idx];
+            let started = Instant::now();
+            let result = self.transport.post(
+                &endpoint.url,
+                &body_str,
+                Duration::from_secs(self.rpc_config.timeout_secs),
+            );
+            crate::metrics::RPC_SECONDS.observe(method, started.elapsed());
+            let (give_up, reason, retry): (CallResult<R>, String, Retry) = match result {
+                Ok(res) if (200..300).contains(&res.status) => {
+                    log::info!(target: "http", "{} {} {}", endpoint.url, label, res.status);
+                    match serde_json::from_str::<R>(&res.body) {
+                        Ok(response) => match rate_limited(&response) {
+                            Retry::No => {
+                                endpoint.succeeded(started.elapsed());
+                                return Ok(response);
+                            }
+                            Retry::FailOver => {
+                                // the node answered, a json-rpc error is not its health
+                                endpoint.succeeded(started.elapsed());
+                                (Ok(response), "json-rpc error".to_string(), Retry::FailOver)
+                            }
+                            retry => (Ok(response), "json-rpc rate limit".to_string(), retry),
+                        },
+                        Err(e) => {
+                            let reason = format!("{} response: {}", label, e);
+                            (
+                                Err(Error::Decode(reason.clone())),
+                                reason,
+                                Retry::Backoff(None),
+                            )
+                        }
+                    }
+                }
+                Ok(res) if res.status == 429 || res.status >= 500 => {
+                    log::info!(target: "http", "{} {} {}", endpoint.url, label, res.status);
+                    (
+                        Err(Error::Transport(
+                            format!("http {}: {}", res.status, res.body).into(),
+                        )),
+                        format!("http {}", res.status),
+                        Retry::Backoff(res.retry_after),
+                    )
+                }
+                Ok(res) => {
+                    log::info!(target: "http", "{} {} {}", endpoint.url, label, res.status);
+                    crate::metrics::RPC_ERRORS.inc_by(method, 1);
+                    return Err(Error::Transport(
+                        format!("http {}: {}", res.status, res.body).into(),
+                    ));
+                }
+                Err(e) => {
+                    log::info!(target: "http", "{} {} {}", endpoint.url, label, e);
+                    let reason = e.to_string();
+                    (Err(e), reason, Retry::Backoff(None))
+                }
+            };
+            // every failed attempt is counted here, once
+            crate::metrics::RPC_ERRORS.inc_by(method, 1);
+            if retry != Retry::FailOver {
+                endpoint.failed();
+            }
+            tried.push(idx);
+            if pinned.is_none() && tried.len() < self.endpoints.len() {
+                log::info!(target: "http", "{} {} failing over: {}", endpoint.url, label, reason);
+                continue;
+            }
+            // no point waiting out a backoff when the process is stopping
+            if retry == Retry::FailOver || attempt >= retries || crate::shutdown::requested() {
+                return give_up;
+            }
+            attempt += 1;
+            tried.clear();
+            let wait = match retry {
+                Retry::Backoff(Some(wait)) => wait,
+                _ => self.rpc_config.backoff(attempt),
+            };
+            log::info!(target: "http",
+                "{} {} retry {}/{} in {}ms: {}",
+                endpoint.url,
+                label,
+                attempt,
+                retries,
+                wait.as_millis(),
+                reason
+            );
+            crate::shutdown::sleep(wait);
+        }
+    }
+}
+
+// newHeads over a websocket. it only tells tail when there is a new block
+// to fetch; blocks and logs still come over http.
+pub struct HeadSubscription {
+    url: String,
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+}
+
+impl Client {
+    pub fn subscribe_new_heads(&self, ws_url: &str) -> CallResult<HeadSubscription> {
+        let (socket, _) =
+            tungstenite::connect(ws_url).map_err(|e| Error::Transport(Box::new(e)))?;
+        let mut subscription = HeadSubscription {
+            url: ws_url.to_owned(),
+            socket,
         };
-        let result = ureq::post(&self.url)
-            .timeout(Duration::new(12, 0))
-            .send_json(&jrpc);
-        match result {
-            Ok(res) => {
-                log::info!(target: "http", "{} {} {} {}", self.url, method, params_str, res.status_text() );
-                let rpc_result = res.into_json::<JsonRpcResult>().unwrap();
-                Ok(rpc_result)
+        let jrpc = JsonRpc {
+            jsonrpc: "2.0".to_string(),
+            id: gen_id(),
+            method: "eth_subscribe".to_string(),
+            params: ParamTypes::Single(("newHeads".to_string(),)),
+        };
+        let request = serde_json::to_string(&jrpc).unwrap();
+        log::info!(target: "http", "{} eth_subscribe newHeads", ws_url);
+        subscription
+            .socket
+            .send(Message::text(request))
+            .map_err(|e| Error::Transport(Box::new(e)))?;
+        subscription.set_read_timeout(Some(Duration::from_secs(self.rpc_config.timeout_secs)))?;
+        loop {
+            let message = subscription.read_json()?;
+            if message["id"] == jrpc.id.as_str() {
+                if let Some(error) = message.get("error") {
+                    let error = serde_json::from_value(error.clone())
+                        .map_err(|e| Error::Decode(format!("eth_subscribe error: {}", e)))?;
+                    return Err(Error::Rpc(error));
+                }
+                log::info!(target: "http", "{} subscribed {}", ws_url, message["result"]);
+                return Ok(subscription);
             }
-            Err(e) => {
-                log::info!(target: "http", "{} {} {} {}", self.url, method, params_str, e);
-                Err(Box::new(e))
+        }
+    }
+}
+
+impl HeadSubscription {
+    // the next head's block number, or None if none arrived within timeout
+    pub fn next_head(&mut self, timeout: Duration) -> CallResult<Option<u32>> {
+        self.set_read_timeout(Some(timeout))?;
+        match self.read_json() {
+            Ok(message) => match message["params"]["result"]["number"].as_str() {
+                Some(number) if message["method"] == "eth_subscription" => {
+                    log::info!(target: "http", "{} newHeads {}", self.url, number);
+                    Ok(Some(hex_to_u32(number)?))
+                }
+                _ => Err(Error::UnexpectedShape(format!("newHeads => {}", message))),
+            },
+            Err(Error::Transport(e)) if is_timeout(e.as_ref()) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_json(&mut self) -> CallResult<serde_json::Value> {
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    return serde_json::from_str(&text)
+                        .map_err(|e| Error::Decode(format!("{}: {}", text, e)))
+                }
+                Ok(Message::Close(frame)) => {
+                    return Err(Error::Transport(Box::from(format!(
+                        "{} closed {:?}",
+                        self.url, frame
+                    ))))
+                }
+                Ok(_) => continue, // ping/pong, answered by tungstenite
+                Err(e) => return Err(Error::Transport(Box::new(e))),
             }
         }
     }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> CallResult<()> {
+        let result = match self.socket.get_mut() {
+            MaybeTlsStream::Plain(stream) => stream.set_read_timeout(timeout),
+            MaybeTlsStream::Rustls(stream) => stream.get_mut().set_read_timeout(timeout),
+            _ => Ok(()),
+        };
+        result.map_err(|e| Error::Transport(Box::new(e)))
+    }
+}
+
+fn is_timeout(e: &(dyn std::error::Error + 'static)) -> bool {
+    match e.downcast_ref::<tungstenite::Error>() {
+        Some(tungstenite::Error::Io(e)) => matches!(
+            e.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ),
+        _ => false,
+    }
+}
+
+fn string_result(method: &str, rpc_result: JsonRpcResult) -> CallResult<String> {
+    match rpc_result.part {
+        RpcResultTypes::Error(e) => Err(Error::Rpc(e.error)),
+        RpcResultTypes::Result(ResultRpc {
+            result: ResultTypes::String(s),
+        }) => Ok(s),
+        RpcResultTypes::Result(r) => Err(Error::UnexpectedShape(format!("{} => {:?}", method, r))),
+    }
+}
+
+// what post does with a json-rpc response that is not a plain result
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Retry {
+    // a result, or an error to hand to the caller
+    No,
+    // an error another node may not give: reverts, unknown methods, logs
+    // ranges over a provider's limit. asked once of every other endpoint.
+    FailOver,
+    // rate limited or a transient node error: fail over, then back off,
+    // after the wait the provider asked for if any
+    Backoff(Option<Duration>),
+}
+
+fn retry_for(part: &RpcResultTypes) -> Retry {
+    let RpcResultTypes::Error(e) = part else {
+        return Retry::No;
+    };
+    let message = e.error.message.to_lowercase();
+    if is_logs_too_large(&message) {
+        Retry::FailOver
+    } else if RATE_LIMIT_CODES.contains(&e.error.code) {
+        Retry::Backoff(
+            e.error
+                .data
+                .as_ref()
+                .and_then(|data| data["rate"]["backoff_seconds"].as_f64())
+                .map(Duration::from_secs_f64),
+        )
+    } else if matches!(e.error.code, -32000 | -32603) && !message.contains("revert") {
+        Retry::Backoff(None)
+    } else {
+        Retry::FailOver
+    }
+}
+
+pub struct EthCall<'a> {
+    pub to: Address,
+    pub abi: &'a Contract,
+    pub function_name: &'a str,
+    pub function_params: Vec<Token>,
+}
+
+impl EthCall<'_> {
+    fn function(&self) -> CallResult<&ethabi::Function> {
+        self.abi
+            .function(self.function_name)
+            .map_err(|e| Error::Decode(format!("abi {}: {}", self.function_name, e)))
+    }
+
+    pub fn encode(&self) -> CallResult<Vec<u8>> {
+        self.function()?
+            .encode_input(&self.function_params)
+            .map_err(|e| Error::Decode(format!("abi {} input: {}", self.function_name, e)))
+    }
+
+    fn params(&self, block_number: Option<u32>) -> CallResult<ParamTypes> {
+        let tx = tx_build(format!("0x{}", hex::encode(self.to)), self.encode()?);
+        Ok(ParamTypes::Infura((tx, infura_block_param(block_number))))
+    }
+
+    fn decode(&self, output: &str) -> CallResult<Vec<Token>> {
+        let output_no_0x = output.strip_prefix("0x").unwrap_or(output);
+        let output_bytes = hex::decode(output_no_0x).map_err(|e| {
+            Error::Decode(format!("{} output {}: {}", self.function_name, output, e))
+        })?;
+        self.decode_bytes(&output_bytes)
+    }
+
+    pub fn decode_bytes(&self, output_bytes: &[u8]) -> CallResult<Vec<Token>> {
+        self.function()?.decode_output(output_bytes).map_err(|err| {
+            Error::Decode(format!(
+                "geth call {}({:?})@0x{} => Error decoding output {:?} {}",
+                self.function_name,
+                self.function_params,
+                hex::encode(self.to),
+                err,
+                hex::encode(output_bytes)
+            ))
+        })
+    }
+}
+
+fn block_result(rpc_result: JsonRpcResult) -> CallResult<InfuraBlock> {
+    match rpc_result.part {
+        RpcResultTypes::Result(ResultRpc {
+            result: ResultTypes::Block(block),
+        }) => Ok(block),
+        RpcResultTypes::Result(result) => {
+            Err(Error::UnexpectedShape(format!("geth.block: {:?}", result)))
+        }
+        RpcResultTypes::Error(err) => Err(Error::Rpc(err.error)),
+    }
+}
+
+fn receipts_result(rpc_result: JsonRpcResult) -> CallResult<Vec<TransactionReceipt>> {
+    match rpc_result.part {
+        RpcResultTypes::Result(ResultRpc {
+            result: ResultTypes::Receipts(receipts),
+        }) => Ok(receipts),
+        RpcResultTypes::Result(ResultRpc {
+            result: ResultTypes::Logs(logs),
+        }) if logs.is_empty() => Ok(vec![]),
+        RpcResultTypes::Result(result) => Err(Error::UnexpectedShape(format!(
+            "eth_getBlockReceipts => {:?}",
+            result
+        ))),
+        RpcResultTypes::Error(err) => Err(Error::Rpc(err.error)),
+    }
+}
+
+// infura: "query returned more than 10000 results". alchemy and geth
+// complain about the response size instead.
+fn is_logs_too_large(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("query returned more than") || message.contains("response size")
+}
+
+fn hex_to_u32(hex: &str) -> CallResult<u32> {
+    u32::from_str_radix(hex.strip_prefix("0x").unwrap_or(hex), 16)
+        .map_err(|e| Error::Decode(format!("{}: {}", hex, e)))
 }
 
 fn infura_block_param(block_number: Option<u32>) -> String {
@@ -157,12 +919,37 @@ fn infura_block_param(block_number: Option<u32>) -> String {
     }
 }
 
+// which logs eth_getLogs returns. empty sets match everything, the node
+// does the filtering so unwanted logs never cross the wire.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    pub addresses: Vec<Address>,
+    // topic0 values, any of
+    pub topics: Vec<String>,
+}
+
+impl LogFilter {
+    fn param(&self, from: u32, to: u32) -> LogsParam {
+        LogsParam {
+            from_block: infura_block_param(Some(from)),
+            to_block: infura_block_param(Some(to)),
+            address: (!self.addresses.is_empty()).then(|| {
+                self.addresses
+                    .iter()
+                    .map(|address| format!("0x{}", hex::encode(address)))
+                    .collect()
+            }),
+            topics: (!self.topics.is_empty()).then(|| vec![self.topics.clone()]),
+        }
+    }
+}
+
 fn tx_build(to: String, data: Vec<u8>) -> JsonRpcParam {
     let mut tx = JsonRpcParam::new();
 
     tx.insert("to".to_string(), to);
     tx.insert("data".to_string(), format!("0x{}", hex::encode(data)));
-    return tx;
+    tx
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -177,6 +964,7 @@ pub struct JsonRpc {
 #[serde(untagged)]
 pub enum ParamTypes {
     Empty,
+    Logs(Vec<LogsParam>),
     Standard(Vec<JsonRpcParam>),
     Single(SingleParam),
     Infura(JsonInfuraRpcParam),
@@ -185,6 +973,17 @@ pub enum ParamTypes {
 }
 
 pub type JsonRpcParam = HashMap<String, String>;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogsParam {
+    pub from_block: String,
+    pub to_block: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topics: Option<Vec<Vec<String>>>,
+}
 pub type SingleParam = (String,);
 pub type InfuraSingleParam = (String, String);
 pub type JsonInfuraRpcParam = (JsonRpcParam, String);
@@ -215,7 +1014,11 @@ pub enum ResultTypes {
     String(String),
     TransactionReceipt(TransactionReceipt),
     Block(InfuraBlock),
+    // blocks fetched without transaction bodies
+    BlockHeader(InfuraBlockHeader),
     Logs(Vec<InfuraLog>),
+    // after Logs, an empty list parses as no logs
+    Receipts(Vec<TransactionReceipt>),
     Null,
 }
 
@@ -230,6 +1033,12 @@ pub struct InfuraLog {
     pub transaction_hash: String,
     #[serde(deserialize_with = "hexstr_to_u32")]
     pub transaction_index: u32,
+    // position in the block, unique per block
+    #[serde(deserialize_with = "hexstr_to_u32")]
+    pub log_index: u32,
+    // taken back by a reorg, only from nodes answering mid-reorg
+    #[serde(default)]
+    pub removed: bool,
     // { "address": "0x8306300ffd616049fd7e4b0354a64da835c1a81c", "blockHash": "0xae7dd19381472fd2d97c18d8e4e4454c9859a2279e882e02f922f924e2fdc558",
     //   "blockNumber": "0x116cfd6", "data": "0x000000000000000000000000000000000000000000000000009778e5c5e0add5", "logIndex": "0x110", "removed": false,
     //   "topics": [ "0x3d0ce9bfc3ed7d6862dbb28b2dea94561fe714a1b4d019aa8af39730d1ad7c3d", "0x0000000000000000000000001f9090aae28b8a3dceadf281b0f12828e676c326" ],
@@ -241,7 +1050,6 @@ impl crate::sql::Ops for InfuraLog {
         let mut fields = vec![
             "address",
             "block_hash",
-            "block_number",
             "data",
             "transaction_hash",
             "transaction_index",
@@ -253,11 +1061,12 @@ impl crate::sql::Ops for InfuraLog {
         fields.append(&mut topic_fields.iter().map(|f| f.as_str()).collect());
 
         let mut values: Vec<Box<dyn ToSql + Sync>> = vec![
-            Box::new(self.address.strip_prefix("0x").unwrap().to_owned()),
-            Box::new(self.block_hash.strip_prefix("0x").unwrap().to_owned()),
             Box::new(
-                i32::from_str_radix(&self.block_number.strip_prefix("0x").unwrap(), 16).unwrap(),
+                i32::from_str_radix(self.block_number.strip_prefix("0x").unwrap(), 16).unwrap(),
             ),
+            Box::new(self.log_index as i32),
+            Box::new(self.address.strip_prefix("0x").unwrap().to_owned()),
+            Box::new(self.block_hash.strip_prefix("0x").unwrap().to_owned()),
             Box::new(self.data.strip_prefix("0x").unwrap().to_owned()),
             Box::new(self.transaction_hash.strip_prefix("0x").unwrap().to_owned()),
             Box::new(self.transaction_index as i32),
@@ -270,7 +1079,102 @@ impl crate::sql::Ops for InfuraLog {
                     .to_owned(),
             ));
         }
-        <dyn crate::Ops>::upsert_sql("logs", vec![], fields, values)
+        <dyn crate::Ops>::upsert_sql("logs", vec!["block_number", "log_index"], fields, values)
+    }
+}
+
+impl InfuraLog {
+    // block number of the log
+    pub fn number(&self) -> CallResult<u32> {
+        hex_to_u32(&self.block_number)
+    }
+
+    // address in indexed topic n, lowercase hex without 0x
+    pub fn topic_address(&self, n: usize) -> CallResult<String> {
+        let topic = self
+            .topics
+            .get(n)
+            .ok_or_else(|| Error::Decode(format!("log has no topic {}", n)))?;
+        let topic = topic.strip_prefix("0x").unwrap_or(topic);
+        if topic.len() != 64 {
+            return Err(Error::Decode(format!("topic {} is not 32 bytes", topic)));
+        }
+        Ok(topic[24..].to_lowercase())
+    }
+
+    // stored logs in from..=to with one of the topic0s, in chain order
+    pub fn find_by_block_range_sql(from: u32, to: u32, topics: &[&str]) -> crate::sql::SqlQuery {
+        let select = sql_query_builder::Select::new()
+            .select("*")
+            .from("logs")
+            .where_clause("block_number >= $1")
+            .where_clause("block_number <= $2")
+            .where_clause("topic0 = ANY($3)")
+            .order_by("block_number, log_index");
+        let topics = topics
+            .iter()
+            .map(|topic| topic.strip_prefix("0x").unwrap_or(topic).to_owned())
+            .collect::<Vec<_>>();
+        (
+            select.to_string(),
+            vec![Box::new(from as i32), Box::new(to as i32), Box::new(topics)],
+        )
+    }
+
+    // stored logs with topic0 the same contract emitted after this one, in
+    // its transaction
+    pub fn find_later_in_transaction_sql(
+        &self,
+        block_number: u32,
+        topic: &str,
+    ) -> crate::sql::SqlQuery {
+        let select = sql_query_builder::Select::new()
+            .select("*")
+            .from("logs")
+            .where_clause("block_number = $1")
+            .where_clause("transaction_index = $2")
+            .where_clause("log_index > $3")
+            .where_clause("address = $4")
+            .where_clause("topic0 = $5")
+            .order_by("log_index");
+        (
+            select.to_string(),
+            vec![
+                Box::new(block_number as i32),
+                Box::new(self.transaction_index as i32),
+                Box::new(self.log_index as i32),
+                Box::new(
+                    self.address
+                        .strip_prefix("0x")
+                        .unwrap_or(&self.address)
+                        .to_lowercase(),
+                ),
+                Box::new(topic.strip_prefix("0x").unwrap_or(topic).to_owned()),
+            ],
+        )
+    }
+}
+
+impl From<&postgres::Row> for InfuraLog {
+    fn from(row: &postgres::Row) -> Self {
+        let hex = |column: &str| format!("0x{}", row.get::<&str, String>(column));
+        let topics = (0..6)
+            .map_while(|topic_num| {
+                row.get::<_, Option<String>>(format!("topic{}", topic_num).as_str())
+            })
+            .map(|topic| format!("0x{}", topic))
+            .collect();
+        InfuraLog {
+            address: hex("address"),
+            block_hash: hex("block_hash"),
+            block_number: format!("0x{:x}", row.get::<&str, i32>("block_number")),
+            data: hex("data"),
+            topics,
+            transaction_hash: hex("transaction_hash"),
+            transaction_index: row.get::<&str, i32>("transaction_index") as u32,
+            log_index: row.get::<&str, i32>("log_index") as u32,
+            removed: false,
+        }
     }
 }
 
@@ -279,13 +1183,14 @@ where
     D: serde::Deserializer<'de>,
 {
     let json_str = String::deserialize(str)?;
-    let num = u32::from_str_radix(json_str.strip_prefix("0x").unwrap(), 16).unwrap();
-    Ok(num)
+    hex_to_u32(&json_str).map_err(serde::de::Error::custom)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InfuraBlock {
     pub hash: String,
+    #[serde(rename = "parentHash")]
+    pub parent_hash: String,
     #[serde(deserialize_with = "hexstr_to_u32")]
     pub number: u32,
     #[serde(deserialize_with = "hexstr_to_u32")]
@@ -293,17 +1198,80 @@ pub struct InfuraBlock {
     pub transactions: Vec<InfuraTransaction>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InfuraBlockHeader {
+    pub hash: String,
+    #[serde(deserialize_with = "hexstr_to_u32")]
+    pub number: u32,
+}
+
 impl InfuraBlock {
     pub fn last_db_block_number(db: &mut crate::sql::Client, descend: bool) -> Option<u32> {
         let sql = Self::last_block_number_sql(descend);
-        match db.q_last(sql) {
-            Some(row) => Some(row.get::<&str, i32>("number") as u32),
-            None => None,
-        }
+        db.q_last(sql)
+            .map(|row| row.get::<&str, i32>("number") as u32)
     }
     fn last_block_number_sql(descend: bool) -> crate::sql::SqlQuery {
         <dyn crate::Ops>::last_column("blocks", "number", descend)
     }
+
+    // highest stored block within from..=to
+    pub fn last_db_block_number_between(
+        db: &mut crate::sql::Client,
+        from: u32,
+        to: u32,
+    ) -> Option<u32> {
+        let select = sql_query_builder::Select::new()
+            .select("max(number) as number")
+            .from("blocks")
+            .where_clause("number >= $1")
+            .where_clause("number <= $2");
+        let sql: crate::sql::SqlQuery = (
+            select.to_string(),
+            vec![Box::new(from as i32), Box::new(to as i32)],
+        );
+        db.q_last(sql)
+            .and_then(|row| row.get::<&str, Option<i32>>("number"))
+            .map(|number| number as u32)
+    }
+
+    // missing from..=to ranges between the first and last stored block
+    pub fn db_gaps(db: &mut crate::sql::Client) -> Vec<(u32, u32)> {
+        db.q(Self::gaps_sql())
+            .iter()
+            .map(|row| {
+                (
+                    row.get::<&str, i32>("gap_from") as u32,
+                    row.get::<&str, i32>("gap_to") as u32,
+                )
+            })
+            .collect()
+    }
+    fn gaps_sql() -> crate::sql::SqlQuery {
+        let numbered = sql_query_builder::Select::new()
+            .select("number")
+            .select("lead(number) OVER (ORDER BY number) AS next_number")
+            .from("blocks");
+        let select = sql_query_builder::Select::new()
+            .select("number + 1 AS gap_from")
+            .select("next_number - 1 AS gap_to")
+            .from(&format!("({}) AS numbered", numbered))
+            .where_clause("next_number > number + 1")
+            .order_by("number");
+        (select.to_string(), vec![])
+    }
+
+    pub fn db_hash(db: &mut crate::sql::Client, number: u32) -> Option<String> {
+        let sql = Self::find_by_number_sql(number);
+        db.q_last(sql).map(|row| row.get::<&str, String>("hash"))
+    }
+    fn find_by_number_sql(number: u32) -> crate::sql::SqlQuery {
+        let select = sql_query_builder::Select::new()
+            .select("*")
+            .from("blocks")
+            .where_clause("number = $1");
+        (select.to_string(), vec![Box::new(number as i32)])
+    }
 }
 
 impl crate::sql::Ops for InfuraBlock {
@@ -324,13 +1292,39 @@ impl crate::sql::Ops for InfuraBlock {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InfuraTransaction {
-    pub transaction_index: String,
+    #[serde(deserialize_with = "hexstr_to_u32")]
+    pub block_number: u32,
+    #[serde(deserialize_with = "hexstr_to_u32")]
+    pub transaction_index: u32,
     pub hash: String,
     pub from: String,
     pub input: String,
+    // None for contract creations
     pub to: Option<String>,
 }
 
+impl crate::sql::Ops for InfuraTransaction {
+    fn to_upsert_sql(&self) -> crate::sql::SqlQuery {
+        <dyn crate::Ops>::upsert_sql(
+            "transactions",
+            vec!["block_number", "transaction_index"],
+            vec!["hash", "from_address", "to_address", "input"],
+            vec![
+                Box::new(self.block_number as i32),
+                Box::new(self.transaction_index as i32),
+                Box::new(self.hash.strip_prefix("0x").unwrap().to_owned()),
+                Box::new(self.from.strip_prefix("0x").unwrap().to_owned()),
+                Box::new(
+                    self.to
+                        .as_ref()
+                        .map(|to| to.strip_prefix("0x").unwrap().to_owned()),
+                ),
+                Box::new(self.input.strip_prefix("0x").unwrap().to_owned()),
+            ],
+        )
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorRpc {
     pub error: ErrorDetailRpc,
@@ -340,6 +1334,8 @@ pub struct ErrorRpc {
 pub struct ErrorDetailRpc {
     pub code: i32,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
 }
 
 impl std::fmt::Display for ErrorDetailRpc {
@@ -375,3 +1371,585 @@ pub fn ethgasstation_fast() -> u64 {
     (gas_prices.fast as f64 * 100_000_000u64 as f64) as u64
 }
 */
+
+#[cfg(test)]
+pub mod fake {
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    // scripted json-rpc node on a local port. the handler gets each request
+    // and returns its {"result": ..} or {"error": ..} part. batches are
+    // answered in reverse order, which json-rpc allows.
+    pub fn serve<F>(handler: F) -> String
+    where
+        F: Fn(&Value) -> Value + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut content_length = 0;
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        if line == "\r\n" {
+                            break;
+                        }
+                        if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                            content_length = len.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    let respond = |request: &Value| {
+                        let mut response = handler(request);
+                        response["jsonrpc"] = "2.0".into();
+                        response["id"] = request["id"].clone();
+                        response
+                    };
+                    let response = match &request {
+                        Value::Array(requests) => {
+                            Value::Array(requests.iter().rev().map(respond).collect())
+                        }
+                        request => respond(request),
+                    };
+                    // {"httpStatus": 429, "retryAfter": "1"} answers at the http level,
+                    // {"rawBody": "..."} with a 200 and that body
+                    let (status, headers, body) = match response.get("httpStatus") {
+                        Some(status) => (
+                            status.as_u64().unwrap(),
+                            format!(
+                                "Retry-After: {}\r\n",
+                                response["retryAfter"].as_str().unwrap_or("")
+                            ),
+                            "".to_string(),
+                        ),
+                        None => match response.get("rawBody") {
+                            Some(body) => (200, "".to_string(), body.as_str().unwrap().to_string()),
+                            None => (200, "".to_string(), response.to_string()),
+                        },
+                    };
+                    write!(
+                        stream,
+                        "HTTP/1.1 {} X\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\n\r\n{}",
+                        status,
+                        headers,
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            }
+        });
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rpc_batch_matches_ids() {
+        let geth = Client::build(&[fake::serve(
+            |request| json!({ "result": request["params"][0] }),
+        )]);
+        let calls = (0..5)
+            .map(|n| ("echo", ParamTypes::Single((n.to_string(),))))
+            .collect();
+
+        let results = geth.rpc_batch(calls).unwrap();
+        let results = results
+            .into_iter()
+            .map(|r| match r.part {
+                RpcResultTypes::Result(ResultRpc {
+                    result: ResultTypes::String(s),
+                }) => s,
+                _ => panic!("{:?}", r),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec!["0", "1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_eth_call_batch_per_call_errors() {
+        let abi = Contract::load(std::fs::File::open("abi/ERC20.json").unwrap()).unwrap();
+        let geth = Client::build(&[fake::serve(|request| {
+            match request["params"][0]["to"].as_str().unwrap() {
+                "0x0000000000000000000000000000000000000001" => json!({
+                    "result": "0x0000000000000000000000000000000000000000000000000000000000000012"
+                }),
+                _ => json!({ "error": { "code": 3, "message": "execution reverted" } }),
+            }
+        })]);
+        let calls = [1u8, 2u8].map(|n| EthCall {
+            to: Address::from_low_u64_be(n as u64),
+            abi: &abi,
+            function_name: "decimals",
+            function_params: vec![],
+        });
+
+        let results = geth.eth_call_batch(&calls, None).unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &vec![Token::Uint(18.into())]);
+        assert!(matches!(
+            &results[1],
+            Err(Error::Rpc(ErrorDetailRpc { code: 3, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_errors_instead_of_panics() {
+        let geth = Client::build(&[fake::serve(|request| {
+            match request["method"].as_str().unwrap() {
+                "eth_blockNumber" => json!({ "result": "0xnothex" }),
+                "eth_getLogs" => json!({ "result": "0x1" }),
+                _ => json!({ "error": { "code": -32000, "message": "header not found" } }),
+            }
+        })])
+        .with_rpc_config(RpcConfig {
+            retries: 0,
+            ..RpcConfig::default()
+        });
+
+        let err = geth.last_block_number().unwrap_err();
+        assert!(matches!(err, Error::Decode(_)));
+        assert!(!err.is_retryable());
+        let err = geth.logs(1, &LogFilter::default()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedShape(_)));
+        let err = geth.block(1).unwrap_err();
+        assert!(matches!(err, Error::Rpc(_)));
+        assert!(err.is_retryable());
+
+        let closed =
+            Client::build(&["http://127.0.0.1:1".to_string()]).with_rpc_config(RpcConfig {
+                retries: 0,
+                ..RpcConfig::default()
+            });
+        let err = closed.last_block_number().unwrap_err();
+        assert!(matches!(err, Error::Transport(_)));
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_db_gaps() {
+        let mut db = crate::sql::test_client();
+        let mut db = crate::sql::TransactionClient::new(&mut db);
+        for number in [900_001, 900_002, 900_005, 900_006, 900_009] {
+            let block = InfuraBlock {
+                hash: format!("0x{:064x}", number),
+                parent_hash: format!("0x{:064x}", number - 1),
+                number,
+                timestamp: 0,
+                transactions: vec![],
+            };
+            db.q(crate::sql::Ops::to_upsert_sql(&block));
+        }
+        let gaps = db
+            .q(InfuraBlock::gaps_sql())
+            .iter()
+            .map(|row| (row.get::<&str, i32>("gap_from"), row.get("gap_to")))
+            .collect::<Vec<(i32, i32)>>();
+        assert_eq!(gaps, vec![(900_003, 900_004), (900_007, 900_008)]);
+    }
+
+    #[test]
+    fn test_block_transactions() {
+        let geth = Client::build(&[fake::serve(|request| {
+            json!({ "result": {
+                "hash": format!("0x{:064x}", 1),
+                "parentHash": format!("0x{:064x}", 0),
+                "number": request["params"][0],
+                "timestamp": "0x0",
+                "transactions": [{
+                    "blockNumber": request["params"][0],
+                    "transactionIndex": "0x2",
+                    "hash": format!("0x{:064x}", 2),
+                    "from": "0x3f5ce5fbfe3e9af3971dd833d26ba9b5c936f0be",
+                    "to": null,
+                    "input": "0x60806040",
+                }],
+            }})
+        })]);
+        let block = geth.block(900_100).unwrap();
+        let transaction = &block.transactions[0];
+        assert_eq!(
+            (transaction.block_number, transaction.transaction_index),
+            (900_100, 2)
+        );
+        assert!(transaction.to.is_none());
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_block_transactions_stored() {
+        let transaction = InfuraTransaction {
+            block_number: 900_100,
+            transaction_index: 2,
+            hash: format!("0x{:064x}", 2),
+            from: "0x3f5ce5fbfe3e9af3971dd833d26ba9b5c936f0be".to_string(),
+            input: "0x60806040".to_string(),
+            to: None,
+        };
+        let mut db = crate::sql::test_client();
+        let mut db = crate::sql::TransactionClient::new(&mut db);
+        db.q(crate::sql::Ops::to_upsert_sql(&transaction));
+        let row = db
+            .first((
+                "SELECT from_address, to_address FROM transactions WHERE block_number = $1"
+                    .to_string(),
+                vec![Box::new(900_100)],
+            ))
+            .unwrap();
+        assert_eq!(
+            row.get::<_, String>("from_address"),
+            "3f5ce5fbfe3e9af3971dd833d26ba9b5c936f0be"
+        );
+        assert_eq!(row.get::<_, Option<String>>("to_address"), None);
+    }
+
+    #[test]
+    fn test_receipts_fall_back_to_transaction_receipts() {
+        let receipt = |index: &str| {
+            json!({
+                "blockNumber": "0xdbba0",
+                "transactionIndex": index,
+                "transactionHash": format!("0x{:0>64}", index.strip_prefix("0x").unwrap()),
+                "status": "0x0",
+                "cumulativeGasUsed": "0xa410",
+                "gasUsed": "0x5208",
+                "effectiveGasPrice": "0x3b9aca00",
+                "contractAddress": null,
+            })
+        };
+        let geth = Client::build(&[fake::serve(move |request| {
+            match request["method"].as_str().unwrap() {
+                "eth_getBlockReceipts" => json!({
+                    "error": { "code": -32601, "message": "the method eth_getBlockReceipts does not exist" }
+                }),
+                _ => {
+                    // transaction hashes are their index
+                    let hash = request["params"][0].as_str().unwrap();
+                    let index = u32::from_str_radix(&hash[2..], 16).unwrap();
+                    json!({ "result": receipt(&format!("0x{:x}", index)) })
+                }
+            }
+        })]);
+        let transaction = |index: u32| InfuraTransaction {
+            block_number: 900_000,
+            transaction_index: index,
+            hash: format!("0x{:064x}", index),
+            from: format!("0x{:040x}", 1),
+            input: "0x".to_string(),
+            to: None,
+        };
+        let block = InfuraBlock {
+            hash: format!("0x{:064x}", 900_000),
+            parent_hash: format!("0x{:064x}", 899_999),
+            number: 900_000,
+            timestamp: 0,
+            transactions: vec![transaction(1), transaction(2)],
+        };
+
+        let receipts = geth.receipts(&[&block]).unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(
+            receipts[0]
+                .iter()
+                .map(|receipt| receipt.transaction_index)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_receipts_stored() {
+        let receipt: TransactionReceipt = serde_json::from_value(json!({
+            "blockNumber": "0xdbba0",
+            "transactionIndex": "0x1",
+            "transactionHash": format!("0x{:064x}", 1),
+            "status": "0x0",
+            "cumulativeGasUsed": "0xa410",
+            "gasUsed": "0x5208",
+            "effectiveGasPrice": "0x3b9aca00",
+            "contractAddress": null,
+        }))
+        .unwrap();
+        let mut db = crate::sql::test_client();
+        let mut db = crate::sql::TransactionClient::new(&mut db);
+        db.q(crate::sql::Ops::to_upsert_sql(&receipt));
+        let row = db
+            .first((
+                "SELECT status, gas_used * effective_gas_price AS gas_cost FROM receipts WHERE block_number = $1"
+                    .to_string(),
+                vec![Box::new(900_000)],
+            ))
+            .unwrap();
+        assert_eq!(row.get::<_, Option<bool>>("status"), Some(false));
+        assert_eq!(row.get::<_, i64>("gas_cost"), 21_000 * 1_000_000_000);
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_stored_logs_rebuild_for_replay() {
+        let mut db = crate::sql::test_client();
+        let geth = crate::transport::fixture_client();
+        let logs = geth.logs(17000000, &LogFilter::default()).unwrap();
+        let mut db = crate::sql::TransactionClient::new(&mut db);
+        for log in &logs {
+            db.q(crate::sql::Ops::to_upsert_sql(log));
+        }
+        let rows = db.q(InfuraLog::find_by_block_range_sql(
+            17000000,
+            17000000,
+            &[crate::uniswap::v2::TOPIC_SWAP],
+        ));
+        let stored = rows.iter().map(InfuraLog::from).collect::<Vec<_>>();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].topics, logs[1].topics);
+        assert_eq!(stored[0].data, logs[1].data);
+        assert_eq!(stored[0].number().unwrap(), 17000000);
+        assert_eq!(stored[0].transaction_index, logs[1].transaction_index);
+    }
+
+    #[test]
+    fn test_tagged_block() {
+        let geth = Client::build(&[fake::serve(|request| {
+            match request["params"][0].as_str().unwrap() {
+                "finalized" => json!({ "result": {
+                    "hash": "0xabc",
+                    "parentHash": "0xabb",
+                    "number": "0x64",
+                    "timestamp": "0x0",
+                    "transactions": ["0xdef"],
+                } }),
+                "safe" => json!({ "result": {
+                    "hash": "0xaaa",
+                    "parentHash": "0xaa9",
+                    "number": "0x60",
+                    "timestamp": "0x0",
+                    "transactions": [],
+                } }),
+                _ => json!({ "result": null }),
+            }
+        })]);
+        let finalized = geth.tagged_block("finalized").unwrap().unwrap();
+        assert_eq!((finalized.number, finalized.hash.as_str()), (100, "0xabc"));
+        // empty blocks parse as a full Block
+        let safe = geth.tagged_block("safe").unwrap().unwrap();
+        assert_eq!((safe.number, safe.hash.as_str()), (96, "0xaaa"));
+        assert!(geth.tagged_block("latest").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_logs_filter_sent_to_node() {
+        let geth = Client::build(&[fake::serve(|request| {
+            let filter = &request["params"][0];
+            assert_eq!(
+                filter["address"],
+                json!(["0x0000000000000000000000000000000000000001"])
+            );
+            assert_eq!(filter["topics"], json!([["0xaa", "0xbb"]]));
+            json!({ "result": [] })
+        })]);
+        let filter = LogFilter {
+            addresses: vec![Address::from_low_u64_be(1)],
+            topics: vec!["0xaa".to_string(), "0xbb".to_string()],
+        };
+        assert!(geth.logs(1, &filter).unwrap().is_empty());
+
+        // an empty filter leaves the keys out so the node matches everything
+        let geth = Client::build(&[fake::serve(|request| {
+            let filter = request["params"][0].as_object().unwrap();
+            assert!(!filter.contains_key("address") && !filter.contains_key("topics"));
+            json!({ "result": [] })
+        })]);
+        assert!(geth.logs(1, &LogFilter::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_logs_range_splits_large_ranges() {
+        let geth = Client::build(&[fake::serve(|request| {
+            let block_param = |key: &str| {
+                let hex = request["params"][0][key].as_str().unwrap();
+                u32::from_str_radix(hex.strip_prefix("0x").unwrap(), 16).unwrap()
+            };
+            let (from, to) = (block_param("fromBlock"), block_param("toBlock"));
+            if to - from > 1 {
+                return json!({ "error": { "code": -32005, "message": "query returned more than 10000 results" } });
+            }
+            let logs = (from..=to)
+                .map(|number| {
+                    json!({
+                        "address": "0x0000000000000000000000000000000000000001",
+                        "blockHash": format!("0x{:064x}", number),
+                        "blockNumber": format!("0x{:x}", number),
+                        "data": "0x",
+                        "topics": [],
+                        "transactionHash": format!("0x{:064x}", 0),
+                        "transactionIndex": "0x0",
+                        "logIndex": "0x0",
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({ "result": logs })
+        })]);
+
+        let logs = geth.logs_range(10, 20, &LogFilter::default()).unwrap();
+        let numbers = logs
+            .iter()
+            .map(|log| u32::from_str_radix(&log.block_number[2..], 16).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, (10..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_rpc_retries_rate_limits() {
+        let attempts = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let geth = Client::build(&[fake::serve(move |_| {
+            match counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => json!({ "httpStatus": 429, "retryAfter": "0" }),
+                1 => json!({ "error": { "code": -32005, "message": "project ID request rate exceeded",
+                                        "data": { "rate": { "backoff_seconds": 0.01 } } } }),
+                _ => json!({ "result": "0x10" }),
+            }
+        })])
+        .with_rpc_config(RpcConfig {
+            backoff_ms: 1,
+            ..RpcConfig::default()
+        });
+
+        assert_eq!(geth.last_block_number().unwrap(), 16);
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_rpc_gives_up_after_retries() {
+        let geth = Client::build(&[fake::serve(
+            |_| json!({ "error": { "code": -32005, "message": "limit exceeded" } }),
+        )])
+        .with_rpc_config(RpcConfig {
+            retries: 2,
+            backoff_ms: 1,
+            ..RpcConfig::default()
+        });
+
+        let err = geth.last_block_number().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Rpc(ErrorDetailRpc { code: -32005, .. })
+        ));
+
+        // three attempts, three errors. a method of its own, other tests
+        // count eth_blockNumber errors too
+        geth.rpc_str("test_gives_up", ParamTypes::Empty)
+            .unwrap_err();
+        assert_eq!(crate::metrics::RPC_ERRORS.get("test_gives_up"), 3);
+    }
+
+    #[test]
+    fn test_failover_to_healthy_endpoint() {
+        let geth = Client::build(&[
+            "http://127.0.0.1:1".to_string(),
+            fake::serve(|_| json!({ "result": [] })),
+        ]);
+
+        assert!(geth.logs(1, &LogFilter::default()).unwrap().is_empty());
+        let health = geth.endpoints_health();
+        assert_eq!(health[0].1.failures, 1);
+        assert_eq!(health[1].1.failures, 0);
+        assert_eq!(geth.ranked(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_failover_on_json_rpc_error_and_bad_body() {
+        let method_not_found = fake::serve(
+            |_| json!({ "error": { "code": -32601, "message": "the method does not exist" } }),
+        );
+        let bad_body = fake::serve(|_| json!({ "rawBody": "<html>gateway</html>" }));
+        let healthy = fake::serve(|_| json!({ "result": "0x10" }));
+        let geth = Client::build(&[method_not_found.clone(), bad_body, healthy]).with_rpc_config(
+            RpcConfig {
+                backoff_ms: 1,
+                ..RpcConfig::default()
+            },
+        );
+        assert_eq!(
+            geth.rpc_str("eth_chainId", ParamTypes::Empty).unwrap(),
+            "0x10"
+        );
+        let health = geth.endpoints_health();
+        // a json-rpc error is an answer, only the bad body counts against health
+        let failures = health.iter().map(|(_, h)| h.failures).collect::<Vec<_>>();
+        assert_eq!(failures, vec![0, 1, 0]);
+
+        // every endpoint gives the same error: it is returned without backoff
+        let geth = Client::build(&[method_not_found.clone(), method_not_found]);
+        let err = geth.rpc_str("eth_chainId", ParamTypes::Empty).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Rpc(ErrorDetailRpc { code: -32601, .. })
+        ));
+    }
+
+    #[test]
+    fn test_no_endpoints() {
+        let err = Client::build(&[])
+            .rpc_str("eth_chainId", ParamTypes::Empty)
+            .unwrap_err();
+        assert!(matches!(err, Error::Transport(_)));
+    }
+
+    #[test]
+    fn test_lagging_endpoint_demoted() {
+        let endpoint = |head: &'static str| fake::serve(move |_| json!({ "result": head }));
+        let geth = Client::build(&[endpoint("0x10"), endpoint("0x20")]);
+
+        assert_eq!(geth.last_block_number().unwrap(), 0x20);
+        assert_eq!(geth.ranked()[0], 1);
+        assert_eq!(
+            geth.rpc_str("eth_chainId", ParamTypes::Empty).unwrap(),
+            "0x20"
+        );
+    }
+
+    #[test]
+    fn test_new_heads_subscription() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let request: serde_json::Value =
+                serde_json::from_str(&socket.read().unwrap().into_text().unwrap()).unwrap();
+            assert_eq!(request["method"], "eth_subscribe");
+            let reply = json!({ "jsonrpc": "2.0", "id": request["id"], "result": "0xabc" });
+            socket.send(Message::text(reply.to_string())).unwrap();
+            let head = json!({ "jsonrpc": "2.0", "method": "eth_subscription",
+                "params": { "subscription": "0xabc", "result": { "number": "0x11" } } });
+            socket.send(Message::text(head.to_string())).unwrap();
+            std::thread::sleep(Duration::from_millis(300));
+            socket.close(None).unwrap();
+            let _ = socket.read();
+        });
+        let geth = Client::build(&[]);
+
+        let mut heads = geth.subscribe_new_heads(&url).unwrap();
+        assert_eq!(heads.next_head(Duration::from_secs(5)).unwrap(), Some(0x11));
+        assert_eq!(heads.next_head(Duration::from_millis(100)).unwrap(), None);
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(heads.next_head(Duration::from_secs(5)).is_err());
+    }
+}
@@ -0,0 +1,1161 @@
+use crate::coin::Coin;
+use crate::config::HandlersConfig;
+use crate::erc20::{self, Erc20};
+use crate::geth::{self, InfuraLog};
+use crate::sql::{self, Ops, TransactionClient};
+use crate::uniswap;
+use crate::uniswap::v2::SwapCall;
+use ethereum_types::{Address, U256};
+use num_traits::Num;
+use pg_bigdecimal::BigInt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::ops::{Div, Mul};
+
+// handler names from_config knows, for config and replay --handler
+pub const NAMES: [&str; 7] = [
+    "swap",
+    "sync",
+    "transfer",
+    "mint",
+    "burn",
+    "lp_transfer",
+    "pair_created",
+];
+
+// decodes logs with one topic0 into its own tables. the registry runs it
+// inside the block's transaction, after the raw log is stored.
+pub trait EventHandler {
+    // name in config.yaml handlers and replay --handler
+    fn name(&self) -> &'static str;
+    fn topic0(&self) -> &'static str;
+    // only logs emitted by these contracts. None for every contract.
+    fn addresses(&self) -> Option<&[Address]> {
+        None
+    }
+    // Ok(false) when the log turned out not to be for this handler, eg a
+    // Transfer of a token that is not a pool. those are not counted.
+    fn handle(&self, log: &InfuraLog, db: &mut TransactionClient) -> Result<bool, Box<dyn Error>>;
+    // delete what handle wrote for block_number, so a replay can run it
+    // again. only needed when handle's writes are not upserts.
+    fn reset_block(&self, _db: &mut TransactionClient, _block_number: u32) {}
+}
+
+// what the registry did with one block's logs
+#[derive(Debug, Default)]
+pub struct Handled {
+    // logs each handler handled or failed on, by handler name
+    pub counts: BTreeMap<&'static str, usize>,
+    // one line per failed handle call
+    pub errors: Vec<String>,
+}
+
+impl Handled {
+    // "swap 1, sync 2"
+    pub fn counts_in_words(counts: &BTreeMap<&'static str, usize>) -> String {
+        if counts.is_empty() {
+            return "nothing".to_string();
+        }
+        counts
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub struct Registry<'a> {
+    handlers: Vec<Box<dyn EventHandler + 'a>>,
+}
+
+impl<'a> Registry<'a> {
+    pub fn from_config(
+        geth: &'a geth::Client,
+        config: &HandlersConfig,
+    ) -> Result<Registry<'a>, Box<dyn Error>> {
+        let mut handlers: Vec<Box<dyn EventHandler + 'a>> = vec![];
+        for name in &config.enabled {
+            let addresses = match config.addresses.get(name) {
+                Some(addresses) => Some(
+                    addresses
+                        .iter()
+                        .map(|address| parse_address(address))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                None => None,
+            };
+            let handler: Box<dyn EventHandler + 'a> = match name.as_str() {
+                "swap" => Box::new(SwapHandler { addresses }),
+                "sync" => Box::new(SyncHandler { geth, addresses }),
+                "transfer" => Box::new(TransferHandler { addresses }),
+                "mint" => Box::new(LiquidityHandler {
+                    name: "mint",
+                    topic0: uniswap::v2::TOPIC_MINT,
+                    addresses,
+                }),
+                "burn" => Box::new(LiquidityHandler {
+                    name: "burn",
+                    topic0: uniswap::v2::TOPIC_BURN,
+                    addresses,
+                }),
+                "lp_transfer" => Box::new(LpTransferHandler { geth, addresses }),
+                "pair_created" => Box::new(PairCreatedHandler {
+                    geth,
+                    // other factories emit the same event for their own pairs
+                    addresses: match addresses {
+                        Some(addresses) => addresses,
+                        None => vec![parse_address(uniswap::v2::UNISWAP_FACTORY)?],
+                    },
+                }),
+                _ => {
+                    return Err(Box::from(format!(
+                        "no handler {}. handlers: {}",
+                        name,
+                        NAMES.join(", ")
+                    )))
+                }
+            };
+            handlers.push(handler);
+        }
+        Ok(Registry { handlers })
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.handlers.iter().map(|handler| handler.name()).collect()
+    }
+
+    pub fn topics(&self) -> Vec<&'static str> {
+        let mut topics = vec![];
+        for handler in &self.handlers {
+            if !topics.contains(&handler.topic0()) {
+                topics.push(handler.topic0());
+            }
+        }
+        topics
+    }
+
+    // just the named handler, None when it is not enabled
+    pub fn only(mut self, name: &str) -> Option<Registry<'a>> {
+        self.handlers.retain(|handler| handler.name() == name);
+        (!self.handlers.is_empty()).then_some(self)
+    }
+
+    // run every matching handler on each log. a failed handler is
+    // reported in Handled and does not stop the others.
+    pub fn handle(&self, db: &mut TransactionClient, logs: &[InfuraLog]) -> Handled {
+        let mut handled = Handled {
+            counts: self.names().into_iter().map(|name| (name, 0)).collect(),
+            errors: vec![],
+        };
+        for log in logs {
+            let address = parse_address(&log.address).ok();
+            for handler in &self.handlers {
+                if log.topics.first().map(String::as_str) != Some(handler.topic0()) {
+                    continue;
+                }
+                if let Some(addresses) = handler.addresses() {
+                    if !address.is_some_and(|address| addresses.contains(&address)) {
+                        continue;
+                    }
+                }
+                let result = handler.handle(log, db);
+                if matches!(result, Ok(false)) {
+                    continue;
+                }
+                *handled.counts.entry(handler.name()).or_default() += 1;
+                crate::metrics::HANDLED.inc_by(handler.name(), 1);
+                if let Err(e) = result {
+                    crate::metrics::HANDLER_ERRORS.inc_by(handler.name(), 1);
+                    handled.errors.push(format!(
+                        "{} tx {:0>3} {}: {}",
+                        handler.name(),
+                        log.transaction_index,
+                        log.address,
+                        e
+                    ));
+                }
+            }
+        }
+        handled
+    }
+
+    pub fn reset_block(&self, db: &mut TransactionClient, block_number: u32) {
+        for handler in &self.handlers {
+            handler.reset_block(db, block_number);
+        }
+    }
+}
+
+fn parse_address(address: &str) -> Result<Address, Box<dyn Error>> {
+    let bytes = hex::decode(address.strip_prefix("0x").unwrap_or(address))?;
+    if bytes.len() != 20 {
+        return Err(Box::from(format!("bad address {}", address)));
+    }
+    Ok(Address::from_slice(&bytes))
+}
+
+// uniswap v2 Swap into swaps, valued in eth from the pool's last reserves
+pub struct SwapHandler {
+    addresses: Option<Vec<Address>>,
+}
+
+impl EventHandler for SwapHandler {
+    fn name(&self) -> &'static str {
+        "swap"
+    }
+
+    fn topic0(&self) -> &'static str {
+        uniswap::v2::TOPIC_SWAP
+    }
+
+    fn addresses(&self) -> Option<&[Address]> {
+        self.addresses.as_deref()
+    }
+
+    fn handle(&self, log: &InfuraLog, db: &mut TransactionClient) -> Result<bool, Box<dyn Error>> {
+        process_swap(db, log, log.number()?)
+    }
+
+    fn reset_block(&self, db: &mut TransactionClient, block_number: u32) {
+        db.q(uniswap::v2::Swap::delete_by_block_sql(block_number));
+    }
+}
+
+// uniswap v2 Sync into reserves, creating the pool on first sight
+pub struct SyncHandler<'a> {
+    geth: &'a geth::Client,
+    addresses: Option<Vec<Address>>,
+}
+
+impl EventHandler for SyncHandler<'_> {
+    fn name(&self) -> &'static str {
+        "sync"
+    }
+
+    fn topic0(&self) -> &'static str {
+        uniswap::v2::TOPIC_SYNC
+    }
+
+    fn addresses(&self) -> Option<&[Address]> {
+        self.addresses.as_deref()
+    }
+
+    fn handle(&self, log: &InfuraLog, db: &mut TransactionClient) -> Result<bool, Box<dyn Error>> {
+        process_sync(self.geth, db, log, log.number()?)?;
+        Ok(true)
+    }
+}
+
+// erc20 Transfer. the raw logs are all there is for now, this only counts.
+pub struct TransferHandler {
+    addresses: Option<Vec<Address>>,
+}
+
+impl EventHandler for TransferHandler {
+    fn name(&self) -> &'static str {
+        "transfer"
+    }
+
+    fn topic0(&self) -> &'static str {
+        erc20::TOPIC_TRANSFER
+    }
+
+    fn addresses(&self) -> Option<&[Address]> {
+        self.addresses.as_deref()
+    }
+
+    fn handle(
+        &self,
+        _log: &InfuraLog,
+        _db: &mut TransactionClient,
+    ) -> Result<bool, Box<dyn Error>> {
+        Ok(true)
+    }
+}
+
+// uniswap v2 Mint or Burn into liquidity_events, for pools already stored.
+// the pair emits Sync first, so the sync handler has created the pool.
+pub struct LiquidityHandler {
+    name: &'static str,
+    topic0: &'static str,
+    addresses: Option<Vec<Address>>,
+}
+
+impl EventHandler for LiquidityHandler {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn topic0(&self) -> &'static str {
+        self.topic0
+    }
+
+    fn addresses(&self) -> Option<&[Address]> {
+        self.addresses.as_deref()
+    }
+
+    fn handle(&self, log: &InfuraLog, db: &mut TransactionClient) -> Result<bool, Box<dyn Error>> {
+        let Some(pool) = stored_pool(db, &log.address) else {
+            log::warn!("{} from unknown pool {}", self.name, log.address);
+            return Ok(false);
+        };
+        let event = uniswap::v2::LiquidityEvent::from_log(&pool, log)?;
+        log::info!(
+            "#{} tx {:0>3} log {}( pool {} sender {} to {:?} amount0 {} amount1 {} )",
+            event.block_number,
+            log.transaction_index,
+            event.kind,
+            log.address.strip_prefix("0x").unwrap(),
+            event.sender,
+            event.to,
+            event.amount0,
+            event.amount1
+        );
+        db.q(event.to_upsert_sql());
+        Ok(true)
+    }
+}
+
+// Transfers of a pool's own LP token into lp_transfers. a new pair's first
+// mint Transfers come before the Sync that would store the pool, so an
+// unknown contract with a Sync later in the transaction is stored here.
+// Transfers of any other token are skipped, a pools and a logs lookup each,
+// so it is off unless listed in handlers.enabled.
+pub struct LpTransferHandler<'a> {
+    geth: &'a geth::Client,
+    addresses: Option<Vec<Address>>,
+}
+
+impl EventHandler for LpTransferHandler<'_> {
+    fn name(&self) -> &'static str {
+        "lp_transfer"
+    }
+
+    fn topic0(&self) -> &'static str {
+        erc20::TOPIC_TRANSFER
+    }
+
+    fn addresses(&self) -> Option<&[Address]> {
+        self.addresses.as_deref()
+    }
+
+    fn handle(&self, log: &InfuraLog, db: &mut TransactionClient) -> Result<bool, Box<dyn Error>> {
+        let pool = match stored_pool(db, &log.address) {
+            Some(pool) => pool,
+            None => {
+                let sync =
+                    log.find_later_in_transaction_sql(log.number()?, uniswap::v2::TOPIC_SYNC);
+                if db.first(sync).is_none() {
+                    return Ok(false);
+                }
+                ensure_pool(self.geth, db, &log.address)?
+            }
+        };
+        let transfer = uniswap::v2::LpTransfer::from_log(&pool, log)?;
+        db.q(transfer.to_upsert_sql());
+        Ok(true)
+    }
+}
+
+// the factory's PairCreated into pools and coins, so new pairs are stored
+// before their first Sync. the tokens come from the log, not token0/token1
+// calls. the pool is stored after both its coins: when a token's metadata
+// call fails there is no pool without coins, and its first Sync tries again.
+pub struct PairCreatedHandler<'a> {
+    geth: &'a geth::Client,
+    addresses: Vec<Address>,
+}
+
+impl EventHandler for PairCreatedHandler<'_> {
+    fn name(&self) -> &'static str {
+        "pair_created"
+    }
+
+    fn topic0(&self) -> &'static str {
+        uniswap::v2::TOPIC_PAIR_CREATED
+    }
+
+    fn addresses(&self) -> Option<&[Address]> {
+        Some(&self.addresses)
+    }
+
+    fn handle(&self, log: &InfuraLog, db: &mut TransactionClient) -> Result<bool, Box<dyn Error>> {
+        let pool = uniswap::v2::Pool::from_pair_created(log)?;
+        log::info!(
+            "#{} tx {:0>3} log pair_created( pool {:x} #{} token0 {:x} token1 {:x} )",
+            log.number()?,
+            log.transaction_index,
+            pool.contract_address,
+            pool.uniswap_v2_index.unwrap_or_default(),
+            pool.token0,
+            pool.token1
+        );
+        create_token(self.geth, db, pool.token0)?;
+        create_token(self.geth, db, pool.token1)?;
+        db.q(pool.to_upsert_sql());
+        Ok(true)
+    }
+}
+
+fn stored_pool(db: &mut TransactionClient, address: &str) -> Option<uniswap::v2::Pool> {
+    let sql = uniswap::v2::Pool::find_by_contract_address(address.into());
+    db.first(sql).map(|row| uniswap::v2::Pool::from(&row))
+}
+
+fn process_sync(
+    geth: &geth::Client,
+    db: &mut sql::TransactionClient,
+    log: &InfuraLog,
+    fetch_block_number: u32,
+) -> Result<(), Box<dyn Error>> {
+    let pool = ensure_pool(geth, db, &log.address)?;
+    let reserves = (
+        U256::from_str_radix(&log.data[2..66], 16).unwrap(),
+        U256::from_str_radix(&log.data[66..130], 16).unwrap(),
+    );
+    log::info!(
+        "#{} tx {:0>3} log sync( pool {} reserves {:?} )",
+        fetch_block_number,
+        log.transaction_index,
+        log.address.strip_prefix("0x").unwrap(),
+        reserves,
+    );
+    update_pool_reserves(db, &pool, fetch_block_number, reserves)?;
+    Ok(())
+}
+
+// false when the pool is not stored
+fn process_swap(
+    db: &mut sql::TransactionClient,
+    log: &InfuraLog,
+    block_number: u32,
+) -> Result<bool, Box<dyn Error>> {
+    let swap_call = SwapCall::try_from(log)?;
+    let sql = uniswap::v2::Pool::find_by_contract_address(log.address.as_str().into());
+    match db.first(sql) {
+        Some(row) => {
+            let pool = uniswap::v2::Pool::from(&row);
+            let mut in0_eth = BigInt::from(0);
+            let mut in1_eth = BigInt::from(0);
+            let sql = uniswap::v2::Reserves::find_by_pool_at(&pool, block_number);
+            match db.first(sql) {
+                Some(row) => {
+                    let reserves = uniswap::v2::Reserves::from_row(&row, &pool);
+                    let x = BigInt::from_str_radix(&reserves.x.to_string(), 10).unwrap();
+                    let y = BigInt::from_str_radix(&reserves.y.to_string(), 10).unwrap();
+                    if is_cash_token(pool.token0) {
+                        in0_eth = swap_call.in0.clone();
+                        in1_eth = if y > BigInt::from(0) {
+                            swap_call.in1.clone().mul(x).div(y)
+                        } else {
+                            BigInt::from(0)
+                        };
+                    } else if is_cash_token(pool.token1) {
+                        in0_eth = if x > BigInt::from(0) {
+                            swap_call.in0.clone().mul(y).div(x)
+                        } else {
+                            BigInt::from(0)
+                        };
+                        in1_eth = swap_call.in1.clone();
+                    }
+                }
+                None => log::info!(
+                    "Warning: swap recorded with no reserves available for pool {}",
+                    pool.contract_address
+                ),
+            }
+            log::info!(
+                "#{} tx {:0>3} log swap( pool {} sender {} to {} in0 {} in0_eth {:?} in1 {} in1_eth {:?} out0 {} out1 {} )",
+                block_number,
+                log.transaction_index,
+                log.address.strip_prefix("0x").unwrap(),
+                swap_call.sender,
+                swap_call.to,
+                swap_call.in0,
+                in0_eth,
+                swap_call.in1,
+                in1_eth,
+                swap_call.out0,
+                swap_call.out1
+            );
+            let swap = uniswap::v2::Swap {
+                pool: &pool,
+                block_number: block_number as u128,
+                transaction_index: log.transaction_index,
+                log_index: log.log_index,
+                in0_eth,
+                in1_eth,
+                call_params: swap_call,
+            };
+            db.q(swap.to_upsert_sql());
+            Ok(true)
+        }
+        None => {
+            log::warn!("process_swap could not find pool in db {}", log.address);
+            Ok(false)
+        }
+    }
+}
+
+fn is_cash_token(token_address: Address) -> bool {
+    let address = format!("{:x}", token_address);
+    match address.as_str() {
+        "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2" => true, // WETH
+        _ => false,
+    }
+}
+
+fn ensure_pool(
+    geth: &geth::Client,
+    db: &mut sql::TransactionClient,
+    address: &str,
+) -> Result<uniswap::v2::Pool, Box<dyn Error>> {
+    let sql = uniswap::v2::Pool::find_by_contract_address(address.into());
+    match db.first(sql) {
+        Some(pool_row) => Ok(uniswap::v2::Pool::from(&pool_row)),
+        None => {
+            let abi_file = std::fs::File::open("abi/uniswap_v2_pair.json").unwrap();
+            let abi_uniswap_pair = ethabi::Contract::load(abi_file).unwrap();
+            let log_address =
+                Address::from_slice(&hex::decode(address.strip_prefix("0x").unwrap()).unwrap());
+            match create_pool(geth, db, &abi_uniswap_pair, log_address, None) {
+                Ok(pool) => Ok(pool),
+                Err(e) => {
+                    log::warn!("pool creation {} failed: {}", hex::encode(log_address), e);
+                    Err(e)
+                }
+            }
+        }
+    }
+}
+
+pub fn create_pool(
+    geth: &geth::Client,
+    db: &mut sql::TransactionClient,
+    abi_pool: &ethabi::Contract,
+    address: Address,
+    uniswap_v2_index: Option<u32>,
+) -> Result<uniswap::v2::Pool, Box<dyn Error>> {
+    let tokens = uniswap::v2::Pool::tokens(geth, abi_pool, &[address])?.remove(0)?;
+    let pool = uniswap::v2::Pool {
+        contract_address: address,
+        token0: tokens.0,
+        token1: tokens.1,
+        uniswap_v2_index,
+        created_block_number: None,
+        created_transaction_index: None,
+    };
+    create_token(geth, db, tokens.0)?;
+    create_token(geth, db, tokens.1)?;
+
+    log::info!("Created {:?}", pool);
+    db.q(pool.to_upsert_sql());
+    Ok(pool)
+}
+
+// create_pool for many pools, reading token0/token1 and the metadata of the
+// coins not stored yet in one multicall each. each pool is stored and
+// committed in its own transaction. Err means the reads failed for all
+pub fn create_pools(
+    geth: &geth::Client,
+    db: &mut sql::Client,
+    abi_pool: &ethabi::Contract,
+    pools: &[(u32, Address)],
+) -> geth::CallResult<Vec<Result<uniswap::v2::Pool, Box<dyn Error>>>> {
+    let addresses = pools
+        .iter()
+        .map(|(_, address)| *address)
+        .collect::<Vec<_>>();
+    let tokens = uniswap::v2::Pool::tokens(geth, abi_pool, &addresses)?;
+    let unstored = tokens
+        .iter()
+        .flatten()
+        .flat_map(|(token0, token1)| [*token0, *token1])
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|address| {
+            db.q(Coin::find_by_contract_address(address.into()))
+                .is_empty()
+        })
+        .collect::<Vec<_>>();
+    let metadata = Erc20::metadata_many(geth, &unstored)?;
+    let coins = unstored
+        .iter()
+        .zip(metadata)
+        .map(|(address, metadata)| (*address, coin_from_metadata(*address, metadata)))
+        .collect::<BTreeMap<_, _>>();
+    Ok(pools
+        .iter()
+        .zip(tokens)
+        .map(|(&(index, address), tokens)| {
+            let (token0, token1) = tokens?;
+            let mut db = TransactionClient::new(db);
+            for token in [token0, token1] {
+                if let Some(coin) = coins.get(&token) {
+                    let coin = coin.as_ref().map_err(|e| e.clone())?;
+                    db.q(coin.to_upsert_sql());
+                    log::info!("Created {:?}", coin);
+                }
+            }
+            let pool = uniswap::v2::Pool {
+                contract_address: address,
+                token0,
+                token1,
+                uniswap_v2_index: Some(index),
+                created_block_number: None,
+                created_transaction_index: None,
+            };
+            db.q(pool.to_upsert_sql());
+            db.client.commit()?;
+            log::info!("Created {:?}", pool);
+            Ok(pool)
+        })
+        .collect())
+}
+
+pub fn update_pool_reserves<'a>(
+    db: &mut sql::TransactionClient,
+    pool: &'a uniswap::v2::Pool,
+    eth_block: u32,
+    reserves: (U256, U256),
+) -> Result<uniswap::v2::Reserves<'a>, Box<dyn Error>> {
+    let pool_reserves = uniswap::v2::Reserves::new(pool, eth_block, reserves);
+    db.q(pool_reserves.to_upsert_sql());
+    Ok(pool_reserves)
+}
+
+fn create_token(
+    geth: &crate::geth::Client,
+    db: &mut sql::TransactionClient,
+    address: Address,
+) -> Result<Coin, Box<dyn Error>> {
+    let rows = db.q(Coin::find_by_contract_address((&address).into()));
+    if rows.is_empty() {
+        let coin = coin_from_metadata(address, Erc20 { address }.metadata(geth)?)?;
+        db.q(coin.to_upsert_sql());
+        log::info!("Created {:?}", coin);
+        Ok(coin)
+    } else {
+        Ok(Coin::from(&rows[0]))
+    }
+}
+
+fn coin_from_metadata(
+    address: Address,
+    (name, symbol, decimals): erc20::Metadata,
+) -> Result<Coin, String> {
+    let mut name = name.unwrap_or_else(|e| {
+        log::info!("warning: token decode fail: {:?}", e);
+        "".to_string()
+    });
+    string_filter_null(&mut name); // psql does not allow nulls
+    let mut symbol = symbol.unwrap_or_else(|e| {
+        log::info!("warning: symbol decode fail: {:?}", e);
+        "".to_string()
+    });
+    string_filter_null(&mut symbol);
+    match decimals {
+        Ok(decimals) => Ok(Coin {
+            contract_address: address,
+            name,
+            symbol,
+            decimals,
+        }),
+        Err(_) => Err(format!("coin decimals() failed for {}", address)),
+    }
+}
+
+fn string_filter_null(str: &mut String) {
+    str.retain(|c| c != '\0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport;
+    use crate::uniswap::v2::{Pool, Reserves};
+
+    #[test]
+    fn test_token0_to_token0_eth() {
+        let pool = Pool {
+            contract_address: [0; 20].into(),
+            token0: [0; 20].into(),
+            token1: [0; 20].into(),
+            uniswap_v2_index: None,
+            created_block_number: None,
+            created_transaction_index: None,
+        };
+        let reserves = Reserves {
+            pool: &pool,
+            block_number: 1,
+            x: U256::from_str_radix("33044264430781", 10).unwrap(),
+            y: U256::from_str_radix("16632437277688007258761", 10).unwrap(),
+        };
+
+        let x = BigInt::from_str_radix(&reserves.x.to_string(), 10).unwrap();
+        let y = BigInt::from_str_radix(&reserves.y.to_string(), 10).unwrap();
+
+        let in0 = BigInt::from_str_radix("1200000000", 10).unwrap();
+        let in0_eth = in0.mul(y).div(x);
+
+        assert_eq!(
+            in0_eth,
+            BigInt::from_str_radix("604005720116248332", 10).unwrap()
+        )
+    }
+
+    // the block of transport::FIXTURE
+    const FIXTURE_BLOCK: u32 = 17000000;
+    const PAIR: &str = "b4e16d0168e52d35cacd2c6185b44281ec28c9dc";
+    const USDC: &str = "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+
+    fn replay() -> geth::Client {
+        erc20::ABI.get_or_init(|| {
+            ethabi::Contract::load(std::fs::File::open("abi/ERC20.json").unwrap()).unwrap()
+        });
+        transport::fixture_client()
+    }
+
+    fn address(hex: &str) -> Address {
+        Address::from_slice(&hex::decode(hex).unwrap())
+    }
+
+    fn pair_abi() -> ethabi::Contract {
+        ethabi::Contract::load(std::fs::File::open("abi/uniswap_v2_pair.json").unwrap()).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_create_token_replay() {
+        let mut db = sql::test_client();
+        let mut db = sql::TransactionClient::new(&mut db);
+        let coin = create_token(&replay(), &mut db, address(USDC)).unwrap();
+        assert_eq!(
+            (coin.name.as_str(), coin.symbol.as_str(), coin.decimals),
+            ("USD Coin", "USDC", 6)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_create_pool_replay() {
+        let mut db = sql::test_client();
+        let mut db = sql::TransactionClient::new(&mut db);
+        let pool = create_pool(&replay(), &mut db, &pair_abi(), address(PAIR), Some(0)).unwrap();
+        assert_eq!((pool.token0, pool.token1), (address(USDC), address(WETH)));
+        let weth = db
+            .first(Coin::find_by_contract_address((&address(WETH)).into()))
+            .unwrap();
+        assert_eq!(Coin::from(&weth).decimals, 18);
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_registry_sync_and_swap_replay() {
+        let mut db = sql::test_client();
+        let geth = replay();
+        let registry = Registry::from_config(&geth, &HandlersConfig::default()).unwrap();
+        let logs = geth
+            .logs(FIXTURE_BLOCK, &geth::LogFilter::default())
+            .unwrap();
+        let mut db = sql::TransactionClient::new(&mut db);
+
+        let handled = registry.handle(&mut db, &logs);
+        assert!(handled.errors.is_empty(), "{:?}", handled.errors);
+        assert_eq!(
+            Handled::counts_in_words(&handled.counts),
+            "burn 0, mint 0, pair_created 0, swap 1, sync 1, transfer 0"
+        );
+        let pool = Pool::from(
+            &db.first(Pool::find_by_contract_address((&address(PAIR)).into()))
+                .unwrap(),
+        );
+        let reserves = db
+            .first(Reserves::find_by_pool_at(&pool, FIXTURE_BLOCK))
+            .unwrap();
+        let reserves = Reserves::from_row(&reserves, &pool);
+        assert_eq!(reserves.block_number, FIXTURE_BLOCK as u128);
+        assert_eq!(reserves.x, U256::from(33044264430781u64));
+        let price = db.first(pool.price_at(FIXTURE_BLOCK + 10)).unwrap();
+        let price = crate::price::Price::from_row(&price).unwrap();
+        assert_eq!(price.block_number, FIXTURE_BLOCK);
+        assert_eq!(crate::price::decimal(&price.price1, 6), "1986.736151");
+        assert!(db.first(pool.price_at(FIXTURE_BLOCK - 1)).is_none());
+        let view = db
+            .first((
+                "SELECT trunc(price1, 6)::text AS price1 FROM prices WHERE pool_contract_address = $1 AND block_number = $2"
+                    .to_string(),
+                vec![Box::new(PAIR), Box::new(FIXTURE_BLOCK as i32)],
+            ))
+            .unwrap();
+        assert_eq!(view.get::<_, String>("price1"), "1986.736151");
+
+        let swap = db
+            .first((
+                "SELECT sender, to_address, in0::text, in0_eth::text FROM swaps WHERE pool_contract_address = $1 AND block_number = $2"
+                    .to_string(),
+                vec![Box::new(PAIR), Box::new(FIXTURE_BLOCK as i32)],
+            ))
+            .unwrap();
+        assert_eq!(
+            swap.get::<_, String>("sender"),
+            "7a250d5630b4cf539739df2c5dacb4c659f2488d"
+        );
+        assert_eq!(
+            swap.get::<_, String>("to_address"),
+            "3f5ce5fbfe3e9af3971dd833d26ba9b5c936f0be"
+        );
+        assert_eq!(swap.get::<_, String>("in0"), "1200000000");
+        assert_eq!(swap.get::<_, String>("in0_eth"), "604005720116248332");
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_swap_uses_reserves_at_its_block() {
+        let mut db = sql::test_client();
+        let mut db = sql::TransactionClient::new(&mut db);
+        let pool = Pool {
+            contract_address: address(PAIR),
+            token0: address(USDC),
+            token1: address(WETH),
+            uniswap_v2_index: None,
+            created_block_number: None,
+            created_transaction_index: None,
+        };
+        db.q(pool.to_upsert_sql());
+        // a replay of an old block, with reserves from later blocks stored
+        let block = FIXTURE_BLOCK - 100;
+        for (block_number, y) in [(block - 5, 1000), (block, 2000), (block + 10, 4000)] {
+            let reserves = Reserves::new(&pool, block_number, (1000.into(), y.into()));
+            db.q(reserves.to_upsert_sql());
+        }
+        let topic = |address: &str| format!("0x{:0>64}", address);
+        let swap = InfuraLog {
+            address: format!("0x{}", PAIR),
+            block_number: format!("0x{:x}", block),
+            topics: vec![
+                uniswap::v2::TOPIC_SWAP.to_string(),
+                topic("aa"),
+                topic("bb"),
+            ],
+            data: format!("0x{:0>64x}{:0>64x}{:0>64x}{:0>64x}", 10, 0, 0, 19),
+            ..Default::default()
+        };
+        assert!(process_swap(&mut db, &swap, block).unwrap());
+
+        let row = db
+            .first((
+                "SELECT in0_eth::text FROM swaps WHERE pool_contract_address = $1 AND block_number = $2"
+                    .to_string(),
+                vec![Box::new(PAIR), Box::new(block as i32)],
+            ))
+            .unwrap();
+        assert_eq!(row.get::<_, String>("in0_eth"), "20");
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_reprocessing_block_is_idempotent() {
+        let mut db = sql::test_client();
+        let geth = replay();
+        let registry = Registry::from_config(&geth, &HandlersConfig::default()).unwrap();
+        let logs = geth
+            .logs(FIXTURE_BLOCK, &geth::LogFilter::default())
+            .unwrap();
+        let mut db = sql::TransactionClient::new(&mut db);
+        for _ in 0..2 {
+            for log in &logs {
+                db.q(log.to_upsert_sql());
+            }
+            registry.handle(&mut db, &logs);
+        }
+        let count = |db: &mut TransactionClient, table: &str| {
+            db.first((
+                format!("SELECT count(*) FROM {} WHERE block_number = $1", table),
+                vec![Box::new(FIXTURE_BLOCK as i32)],
+            ))
+            .unwrap()
+            .get::<_, i64>("count")
+        };
+        assert_eq!(count(&mut db, "logs"), 2);
+        assert_eq!(count(&mut db, "swaps"), 1);
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_mint_and_lp_transfer() {
+        let mut db = sql::test_client();
+        let geth = geth::Client::build(&["http://127.0.0.1:1".to_string()]);
+        let config = HandlersConfig {
+            enabled: vec!["lp_transfer".to_string(), "mint".to_string()],
+            ..Default::default()
+        };
+        let registry = Registry::from_config(&geth, &config).unwrap();
+        let mut db = sql::TransactionClient::new(&mut db);
+        let pool = Pool {
+            contract_address: address(PAIR),
+            token0: address(USDC),
+            token1: address(WETH),
+            uniswap_v2_index: None,
+            created_block_number: None,
+            created_transaction_index: None,
+        };
+        db.q(pool.to_upsert_sql());
+
+        let topic = |address: &str| format!("0x{:0>64}", address);
+        let log = |address: &str, log_index: u32, topics: Vec<String>, data: String| InfuraLog {
+            address: format!("0x{}", address),
+            block_number: "0x1".to_string(),
+            topics,
+            data,
+            log_index,
+            ..Default::default()
+        };
+        let lp = "00000000000000000000000000000000000000aa";
+        let router = "7a250d5630b4cf539739df2c5dacb4c659f2488d";
+        let logs = [
+            // LP tokens minted to lp
+            log(
+                PAIR,
+                0,
+                vec![erc20::TOPIC_TRANSFER.to_string(), topic("0"), topic(lp)],
+                format!("0x{:0>64x}", 5000),
+            ),
+            // a USDC transfer, not an LP token
+            log(
+                USDC,
+                1,
+                vec![erc20::TOPIC_TRANSFER.to_string(), topic(lp), topic(router)],
+                format!("0x{:0>64x}", 9),
+            ),
+            log(
+                PAIR,
+                2,
+                vec![uniswap::v2::TOPIC_MINT.to_string(), topic(router)],
+                format!("0x{:0>64x}{:0>64x}", 100, 200),
+            ),
+        ];
+        let handled = registry.handle(&mut db, &logs);
+        assert!(handled.errors.is_empty(), "{:?}", handled.errors);
+        // the USDC transfer is not counted
+        assert_eq!(handled.counts["lp_transfer"], 1);
+
+        let transfers = db.q((
+            "SELECT from_address, to_address, value::text FROM lp_transfers WHERE block_number = 1"
+                .to_string(),
+            vec![],
+        ));
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].get::<_, String>("to_address"), lp);
+        assert_eq!(transfers[0].get::<_, String>("value"), "5000");
+        let mint = db
+            .first((
+                "SELECT kind, sender, to_address, amount1::text FROM liquidity_events WHERE block_number = 1 AND log_index = 2"
+                    .to_string(),
+                vec![],
+            ))
+            .unwrap();
+        assert_eq!(mint.get::<_, String>("kind"), "mint");
+        assert_eq!(mint.get::<_, String>("sender"), router);
+        assert_eq!(mint.get::<_, Option<String>>("to_address"), None);
+        assert_eq!(mint.get::<_, String>("amount1"), "200");
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_first_mint_lp_transfers() {
+        let mut db = sql::test_client();
+        let geth = replay();
+        let config = HandlersConfig {
+            enabled: NAMES.map(String::from).to_vec(),
+            ..Default::default()
+        };
+        let registry = Registry::from_config(&geth, &config).unwrap();
+        let mut db = sql::TransactionClient::new(&mut db);
+
+        // the first deposit into PAIR: its LP Transfers come before the Sync
+        // that stores the pool
+        let topic = |address: &str| format!("0x{:0>64}", address);
+        let lp = "00000000000000000000000000000000000000aa";
+        let router = "7a250d5630b4cf539739df2c5dacb4c659f2488d";
+        let log = |log_index: u32, topics: Vec<String>, data: String| InfuraLog {
+            address: format!("0x{}", PAIR),
+            block_hash: format!("0x{:064x}", 1),
+            block_number: format!("0x{:x}", FIXTURE_BLOCK),
+            transaction_hash: format!("0x{:064x}", 2),
+            topics,
+            data,
+            log_index,
+            ..Default::default()
+        };
+        let transfer = |to: &str| vec![erc20::TOPIC_TRANSFER.to_string(), topic("0"), topic(to)];
+        let logs = [
+            // MINIMUM_LIQUIDITY, locked at 0x0
+            log(0, transfer("0"), format!("0x{:0>64x}", 1000)),
+            log(1, transfer(lp), format!("0x{:0>64x}", 5000)),
+            log(
+                2,
+                vec![uniswap::v2::TOPIC_SYNC.to_string()],
+                format!("0x{:0>64x}{:0>64x}", 100, 200),
+            ),
+            log(
+                3,
+                vec![uniswap::v2::TOPIC_MINT.to_string(), topic(router)],
+                format!("0x{:0>64x}{:0>64x}", 100, 200),
+            ),
+        ];
+        for log in &logs {
+            db.q(log.to_upsert_sql());
+        }
+        let handled = registry.handle(&mut db, &logs);
+        assert!(handled.errors.is_empty(), "{:?}", handled.errors);
+        assert_eq!(handled.counts["lp_transfer"], 2);
+        assert_eq!(handled.counts["sync"], 1);
+        assert_eq!(handled.counts["mint"], 1);
+
+        let transfers = db.q((
+            "SELECT to_address, value::text FROM lp_transfers WHERE block_number = $1 ORDER BY log_index"
+                .to_string(),
+            vec![Box::new(FIXTURE_BLOCK as i32)],
+        ));
+        let transfers = transfers
+            .iter()
+            .map(|row| {
+                (
+                    row.get::<_, String>("to_address"),
+                    row.get::<_, String>("value"),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transfers,
+            vec![
+                ("0".repeat(40), "1000".to_string()),
+                (lp.to_string(), "5000".to_string())
+            ]
+        );
+
+        // a Transfer of a token with no Sync after it is still skipped
+        let usdc = InfuraLog {
+            address: format!("0x{}", USDC),
+            ..log(4, transfer(lp), format!("0x{:0>64x}", 9))
+        };
+        db.q(usdc.to_upsert_sql());
+        let handled = registry.handle(&mut db, &[usdc]);
+        assert_eq!(handled.counts["lp_transfer"], 0);
+        assert_eq!(handled.counts["transfer"], 1);
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_pair_created_replay() {
+        let mut db = sql::test_client();
+        let geth = replay();
+        let config = HandlersConfig {
+            enabled: vec!["pair_created".to_string()],
+            ..Default::default()
+        };
+        let registry = Registry::from_config(&geth, &config).unwrap();
+        let mut db = sql::TransactionClient::new(&mut db);
+        let topic = |address: &str| format!("0x{:0>64}", address);
+        let pair_created = |emitter: &str| InfuraLog {
+            address: format!("0x{}", emitter),
+            block_number: "0x9a5a5e".to_string(),
+            topics: vec![
+                uniswap::v2::TOPIC_PAIR_CREATED.to_string(),
+                topic(USDC),
+                topic(WETH),
+            ],
+            // pair, then allPairs.length after the push
+            data: format!("0x{:0>64}{:0>64x}", PAIR, 12),
+            transaction_index: 3,
+            ..Default::default()
+        };
+        // the same event from another factory is not a uniswap v2 pair
+        let logs = [
+            pair_created(uniswap::v2::UNISWAP_FACTORY),
+            pair_created(USDC),
+        ];
+        let handled = registry.handle(&mut db, &logs);
+        assert!(handled.errors.is_empty(), "{:?}", handled.errors);
+        assert_eq!(handled.counts["pair_created"], 1);
+
+        let pool = Pool::from(
+            &db.first(Pool::find_by_contract_address((&address(PAIR)).into()))
+                .unwrap(),
+        );
+        assert_eq!((pool.token0, pool.token1), (address(USDC), address(WETH)));
+        assert_eq!(pool.uniswap_v2_index, Some(11));
+        assert_eq!(pool.created_block_number, Some(0x9a5a5e));
+        assert_eq!(pool.created_transaction_index, Some(3));
+        for token in [USDC, WETH] {
+            assert!(db
+                .first(Coin::find_by_contract_address((&address(token)).into()))
+                .is_some());
+        }
+
+        // no metadata for token1 in the fixture: the pair is not stored
+        // without its coins
+        let unknown = "00000000000000000000000000000000000000bb";
+        let pair = "00000000000000000000000000000000000000cc";
+        let log = InfuraLog {
+            topics: vec![
+                uniswap::v2::TOPIC_PAIR_CREATED.to_string(),
+                topic(USDC),
+                topic(unknown),
+            ],
+            data: format!("0x{:0>64}{:0>64x}", pair, 13),
+            ..pair_created(uniswap::v2::UNISWAP_FACTORY)
+        };
+        let handled = registry.handle(&mut db, &[log]);
+        assert_eq!(handled.errors.len(), 1);
+        assert!(db
+            .first(Pool::find_by_contract_address((&address(pair)).into()))
+            .is_none());
+    }
+
+    #[test]
+    fn test_registry_from_config() {
+        let geth = geth::Client::build(&["http://127.0.0.1:1".to_string()]);
+        let config = HandlersConfig {
+            enabled: vec!["sync".to_string(), "swap".to_string()],
+            addresses: BTreeMap::from([("swap".to_string(), vec![format!("0x{}", PAIR)])]),
+        };
+        let registry = Registry::from_config(&geth, &config).unwrap();
+        assert_eq!(registry.names(), vec!["sync", "swap"]);
+        assert_eq!(
+            registry.topics(),
+            vec![uniswap::v2::TOPIC_SYNC, uniswap::v2::TOPIC_SWAP]
+        );
+        assert!(registry.only("transfer").is_none());
+        let unknown = HandlersConfig {
+            enabled: vec!["flash".to_string()],
+            ..Default::default()
+        };
+        assert!(Registry::from_config(&geth, &unknown).is_err());
+        let default = Registry::from_config(&geth, &HandlersConfig::default()).unwrap();
+        assert!(!default.names().contains(&"lp_transfer"));
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_registry_addresses() {
+        let geth = geth::Client::build(&["http://127.0.0.1:1".to_string()]);
+        let config = HandlersConfig {
+            enabled: vec!["swap".to_string()],
+            addresses: BTreeMap::from([("swap".to_string(), vec![format!("0x{}", PAIR)])]),
+        };
+        // swap only runs for PAIR, so a swap from another contract is skipped
+        let registry = Registry::from_config(&geth, &config).unwrap();
+        let mut db = sql::test_client();
+        let mut db = sql::TransactionClient::new(&mut db);
+        let log = InfuraLog {
+            address: format!("0x{}", USDC),
+            topics: vec![uniswap::v2::TOPIC_SWAP.to_string()],
+            ..Default::default()
+        };
+        let handled = registry.handle(&mut db, &[log]);
+        assert_eq!(handled.counts["swap"], 0);
+    }
+}
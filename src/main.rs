@@ -1,41 +1,64 @@
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::coin::Coin;
-use crate::erc20::Erc20;
-use crate::geth::{InfuraBlock, InfuraLog};
+use crate::geth::{InfuraBlock, InfuraLog, TransactionReceipt};
 use crate::sql::Ops;
-use crate::uniswap::v2::SwapCall;
-use ethereum_types::{Address, U256};
-use num_traits::Num;
-use pg_bigdecimal::BigInt;
-use std::ops::{Div, Mul};
+use ethereum_types::Address;
 
 mod coin;
 mod config;
 mod curve;
 mod erc20;
+mod finality;
 mod geth;
+mod handlers;
 mod log;
+mod metrics;
+mod multicall;
+mod price;
+mod reorg;
+mod replay;
+mod shutdown;
 mod sql;
+mod transport;
 mod uniswap;
 
+// blocks behind the chain head before tail switches to range fetches
+const CATCHUP_RANGE: u32 = 100;
+// how often tail asks for the chain head when caught up
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
 fn main() {
     log::init();
+    shutdown::install();
     config::CONFIG.set(config::load("config.yaml")).unwrap();
     log::info!("poolpoll");
 
     let config = config::CONFIG.get().unwrap();
+    if let Some(listen) = &config.metrics.listen {
+        if let Err(e) = metrics::serve(listen) {
+            log::warn!("metrics on {} failed: {}", listen, e);
+        }
+    }
     let mut sql = sql::new();
 
-    let geth = geth::Client::build(&config.geth_url);
+    let geth = geth::Client::build(&config.geth_endpoints())
+        .with_rpc_config(config.rpc.clone())
+        .with_transport(transport::from_config(&config.rpc));
     let abi_file = std::fs::File::open("abi/ERC20.json").unwrap();
     erc20::ABI
         .set(ethabi::Contract::load(abi_file).unwrap())
         .unwrap();
+    let handlers = handlers::Registry::from_config(&geth, &config.handlers)
+        .unwrap_or_else(|err| panic!("config.yaml handlers: {}", err));
+    log::info!("handlers: {}", handlers.names().join(", "));
 
-    let last_chain_block_number = geth.last_block_number();
+    let last_chain_block_number = geth.last_block_number().unwrap();
+    geth.log_health();
     log::info!("ethereum mainnet latest block #{}", last_chain_block_number);
     let first_db_block_number =
         InfuraBlock::last_db_block_number(&mut sql, false).unwrap_or(last_chain_block_number);
@@ -49,42 +72,332 @@ fn main() {
         db_block_range,
         db_block_range as f32 * 12.0 / 60.0 / 60.0 / 60.0
     );
-    if std::env::args().find(|arg| arg == "discover").is_some() {
-        discover(&geth, &mut sql);
+    if std::env::args().any(|arg| arg == "backfill") {
+        let (Some(from), Some(to)) = (arg_value("--from"), arg_value("--to")) else {
+            log::info!("backfill --from <block> --to <block> [--workers <n>]");
+            return;
+        };
+        let mut backfill_config = config.backfill.clone();
+        if let Some(workers) = arg_value("--workers") {
+            backfill_config.workers = workers;
+        }
+        backfill(
+            &geth,
+            &mut sql,
+            from,
+            to,
+            &backfill_config,
+            &Ingest::new(&config.logs, handlers),
+        );
+    } else if std::env::args().any(|arg| arg == "gaps") {
+        gaps(
+            &geth,
+            &mut sql,
+            std::env::args().any(|arg| arg == "--repair"),
+            &config.backfill,
+            &Ingest::new(&config.logs, handlers),
+        );
+    } else if std::env::args().any(|arg| arg == "replay") {
+        let (Some(from), Some(to)) = (arg_value("--from"), arg_value("--to")) else {
+            log::info!("replay --from <block> --to <block> [--handler <name>] [--restart]");
+            return;
+        };
+        replay(
+            handlers,
+            &mut sql,
+            from,
+            to,
+            arg_value::<String>("--handler").as_deref(),
+            std::env::args().any(|arg| arg == "--restart"),
+        );
+    } else if std::env::args().find(|arg| arg == "discover").is_some() {
+        discover(
+            &geth,
+            &mut sql,
+            arg_value("--from-index"),
+            arg_value("--to-index"),
+        );
+    } else if std::env::args().any(|arg| arg == "price") {
+        let Some(pool) = arg_value::<String>("--pool") else {
+            log::info!("price --pool <address> [--block <n>]");
+            return;
+        };
+        price(
+            &mut sql,
+            &pool,
+            arg_value("--block").unwrap_or(last_db_block_number),
+        );
     } else if std::env::args().find(|arg| arg == "refresh").is_some() {
         refresh(&geth, &mut sql, last_chain_block_number);
     } else if std::env::args().find(|arg| arg == "tail").is_some() {
+        let confirmations = config.tail.confirmations;
+        let confirmed_block_number = last_chain_block_number.saturating_sub(confirmations);
         let mut start_block = last_db_block_number;
         if std::env::args().find(|arg| arg == "--latest").is_some() {
-            start_block = last_chain_block_number;
+            start_block = confirmed_block_number;
         }
-        tail(&geth, &mut sql, start_block, last_chain_block_number);
+        let mut ws_url = None;
+        if std::env::args().any(|arg| arg == "--ws") {
+            ws_url = config.geth_ws_url.as_deref();
+            if ws_url.is_none() {
+                log::warn!("tail --ws: no geth_ws_url in config, polling instead");
+            }
+        }
+        tail(
+            &geth,
+            &mut sql,
+            start_block,
+            confirmed_block_number,
+            confirmations,
+            ws_url,
+            &Ingest {
+                receipts: config.tail.receipts,
+                ..Ingest::new(&config.logs, handlers)
+            },
+        );
     } else {
-        log::info!("commands: discover, refresh, tail")
+        log::info!("commands: backfill, discover, gaps, price, refresh, replay, tail")
     }
 }
 
+// the value after a command line flag, eg --from 100
+fn arg_value<T: std::str::FromStr>(flag: &str) -> Option<T> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let idx = args.iter().position(|arg| arg == flag)?;
+    args.get(idx + 1)?.parse().ok()
+}
+
+// fill from..=to with worker threads fetching ranges in parallel. ranges
+// are committed strictly in block order so reserves arrive oldest first.
+// blocks already stored at the start are skipped, so rerunning the same
+// command after a crash resumes where the last run stopped.
+fn backfill(
+    geth: &geth::Client,
+    db: &mut sql::Client,
+    from: u32,
+    to: u32,
+    config: &config::BackfillConfig,
+    ingest: &Ingest,
+) {
+    let start = InfuraBlock::last_db_block_number_between(db, from, to).map_or(from, |n| n + 1);
+    let ranges = backfill_ranges(start, to, config.range);
+    let workers = config.workers.max(1);
+    log::info!(
+        "backfill #{}-#{} from #{}: {} ranges, {} workers",
+        from,
+        to,
+        start,
+        ranges.len(),
+        workers
+    );
+    let started = std::time::Instant::now();
+    let next = AtomicUsize::new(0);
+    let committed = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let mut summary = Summary::default();
+    let (tx, rx) = mpsc::channel();
+    let log_filter = &ingest.log_filter;
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (ranges, next, committed, stop) = (&ranges, &next, &committed, &stop);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                if idx >= ranges.len() {
+                    break;
+                }
+                // stay near the commit point so fetched ranges don't pile up
+                while idx > committed.load(Ordering::SeqCst) + workers * 2 {
+                    if stop.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                let (range_from, range_to) = ranges[idx];
+                let result = fetch_range(geth, range_from, range_to, log_filter);
+                if stop.load(Ordering::SeqCst) || tx.send((idx, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut fetched = BTreeMap::new();
+        'commit: for (idx, result) in rx {
+            fetched.insert(idx, result);
+            while let Some(result) = fetched.remove(&committed.load(Ordering::SeqCst)) {
+                let (range_from, range_to) = ranges[committed.load(Ordering::SeqCst)];
+                match result {
+                    Ok(blocks) => {
+                        for (block, logs) in blocks {
+                            if shutdown::requested() {
+                                stop.store(true, Ordering::SeqCst);
+                                break 'commit;
+                            }
+                            process_logs_and_mark_block(
+                                &ingest.handlers,
+                                db,
+                                block.number,
+                                logs,
+                                &[],
+                                &block,
+                                &mut summary,
+                            );
+                        }
+                        let done = committed.fetch_add(1, Ordering::SeqCst) + 1;
+                        let blocks_done = range_to - start + 1;
+                        log::info!(
+                            "backfill #{} committed. {}/{} ranges. {:.1} blocks/sec",
+                            range_to,
+                            done,
+                            ranges.len(),
+                            blocks_done as f32 / started.elapsed().as_secs_f32()
+                        );
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "backfill range #{}-#{} failed: {}. rerun to resume",
+                            range_from,
+                            range_to,
+                            e
+                        );
+                        stop.store(true, Ordering::SeqCst);
+                        break 'commit;
+                    }
+                }
+            }
+        }
+    });
+    if let Err(e) = finality::mark(geth, db) {
+        log::info!("finality marking failed: {}", e);
+    }
+    summary.log("backfill");
+}
+
+// report blocks missing between the first and last stored block, the
+// ones whose processing failed and rolled back. --repair backfills them.
+fn gaps(
+    geth: &geth::Client,
+    db: &mut sql::Client,
+    repair: bool,
+    config: &config::BackfillConfig,
+    ingest: &Ingest,
+) {
+    let gaps = InfuraBlock::db_gaps(db);
+    let missing = gaps.iter().map(|(from, to)| to - from + 1).sum::<u32>();
+    for (from, to) in &gaps {
+        log::info!("gap #{}-#{} ({} blocks)", from, to, to - from + 1);
+    }
+    log::info!("{} gaps, {} blocks missing", gaps.len(), missing);
+    if repair {
+        for (from, to) in gaps {
+            if shutdown::requested() {
+                break;
+            }
+            backfill(geth, db, from, to, config, ingest);
+        }
+        let left = InfuraBlock::db_gaps(db);
+        log::info!("repair done. {} gaps left", left.len());
+    }
+}
+
+// from..=to in chunks of size blocks
+fn backfill_ranges(from: u32, to: u32, size: u32) -> Vec<(u32, u32)> {
+    let size = size.max(1);
+    (from..=to)
+        .step_by(size as usize)
+        .map(|range_from| (range_from, range_from.saturating_add(size - 1).min(to)))
+        .collect()
+}
+
+// last_chain_block_number is the head tail ingests up to, confirmations
+// behind the real chain head
 fn tail(
     geth: &geth::Client,
     db: &mut sql::Client,
     mut db_block_number: u32,
     mut last_chain_block_number: u32,
+    confirmations: u32,
+    ws_url: Option<&str>,
+    ingest: &Ingest,
 ) {
-    loop {
+    let mut heads = None;
+    let mut summary = Summary::default();
+    while !shutdown::requested() {
         let started = std::time::Instant::now();
         log::info!(
             "tail db_block_number {} chain_block_number {}",
             db_block_number,
             last_chain_block_number
         );
-        if db_block_number < last_chain_block_number {
+        let chain_head = last_chain_block_number + confirmations;
+        metrics::CHAIN_HEAD.set(chain_head as f64);
+        metrics::DB_HEAD.set(db_block_number as f64);
+        metrics::LAG_BLOCKS.set(chain_head.saturating_sub(db_block_number) as f64);
+        if last_chain_block_number > db_block_number + CATCHUP_RANGE {
+            let to_block_number = db_block_number + CATCHUP_RANGE;
+            match tail_range(
+                geth,
+                db,
+                db_block_number + 1,
+                to_block_number,
+                ingest,
+                &mut summary,
+            ) {
+                Ok(last_block) => {
+                    let elapsed_secs = started.elapsed().as_secs_f32();
+                    db_block_number =
+                        InfuraBlock::last_db_block_number(db, true).unwrap_or(db_block_number);
+                    if let Some(block) = last_block {
+                        metrics::LAG_SECONDS.set(seconds_since_block(&block) as f64);
+                        log::info!(
+                            "processed range in {:.1} seconds. db #{}. eth #{}. {} blocks / {} behind.",
+                            elapsed_secs,
+                            db_block_number,
+                            last_chain_block_number,
+                            last_chain_block_number - db_block_number,
+                            elapsed_in_words(seconds_since_block(&block)),
+                        );
+                    }
+                }
+                Err(e) => {
+                    log::info!(
+                        "range #{}-#{} failed: {}",
+                        db_block_number + 1,
+                        to_block_number,
+                        e
+                    );
+                    if !is_retryable(e.as_ref()) {
+                        break;
+                    }
+                }
+            }
+        } else if db_block_number < last_chain_block_number {
             let fetch_block_number = db_block_number + 1;
             match geth.block(fetch_block_number) {
-                Ok(block) => match geth.logs(fetch_block_number) {
-                    Ok(logs) => {
-                        process_logs_and_mark_block(geth, db, fetch_block_number, logs, &block);
+                Ok(block) if is_orphaned_parent(db, &block) => {
+                    match rollback_reorg(geth, db, &block) {
+                        Ok(ancestor) => db_block_number = ancestor,
+                        Err(e) => log::warn!("reorg rollback failed: {}", e),
+                    }
+                }
+                Ok(block) => match geth
+                    .logs(fetch_block_number, &ingest.log_filter)
+                    .and_then(|logs| Ok((logs, fetch_receipts(geth, ingest, &[&block])?)))
+                {
+                    Ok((logs, mut receipts)) => {
+                        process_logs_and_mark_block(
+                            &ingest.handlers,
+                            db,
+                            fetch_block_number,
+                            logs,
+                            &receipts.pop().unwrap_or_default(),
+                            &block,
+                            &mut summary,
+                        );
                         let elapsed_secs = started.elapsed().as_secs_f32();
                         db_block_number = InfuraBlock::last_db_block_number(db, true).unwrap();
+                        metrics::LAG_SECONDS.set(seconds_since_block(&block) as f64);
                         log::info!(
                             "processed in {:.1} seconds. db #{}. eth #{}. {} blocks / {} behind.",
                             elapsed_secs,
@@ -95,47 +408,298 @@ fn tail(
                         );
                     }
                     Err(e) => {
-                        log::info!("block {} logs fetch failed: {:?}", fetch_block_number, e)
+                        log::info!(
+                            "block {} logs or receipts fetch failed: {}",
+                            fetch_block_number,
+                            e
+                        );
+                        if !e.is_retryable() {
+                            break;
+                        }
                     }
                 },
-                Err(e) => log::info!("tail_from eth block get failed {:?}", e),
+                Err(e) => {
+                    log::info!("tail_from eth block get failed {}", e);
+                    if !e.is_retryable() {
+                        break;
+                    }
+                }
             }
         }
 
         // are we caught up?
         if db_block_number >= last_chain_block_number {
-            last_chain_block_number = geth.last_block_number();
+            match geth.last_block_number() {
+                Ok(number) => last_chain_block_number = number.saturating_sub(confirmations),
+                Err(e) => {
+                    log::info!("eth block number get failed {}", e);
+                    if !e.is_retryable() {
+                        break;
+                    }
+                }
+            }
+            if let Err(e) = finality::mark(geth, db) {
+                log::info!("finality marking failed: {}", e);
+            }
             if db_block_number >= last_chain_block_number {
-                log::info!(
-                    "sleeping 10 sec at db #{} eth #{}",
+                wait_for_block(
+                    geth,
+                    ws_url,
+                    &mut heads,
                     db_block_number,
-                    last_chain_block_number
+                    last_chain_block_number,
                 );
-                thread::sleep(Duration::from_secs(10)); // then sleep
             }
         }
     }
+    if shutdown::requested() {
+        log::info!("tail stopped at db #{} on shutdown", db_block_number);
+    } else {
+        log::warn!("tail stopped at db #{} on a fatal error", db_block_number);
+    }
+    summary.log("tail");
 }
 
-fn process_logs_and_mark_block(
+// with a websocket url, wake on the next newHeads notification. without
+// one, or while the socket is down, poll every 10 seconds.
+fn wait_for_block(
+    geth: &geth::Client,
+    ws_url: Option<&str>,
+    heads: &mut Option<geth::HeadSubscription>,
+    db_block_number: u32,
+    last_chain_block_number: u32,
+) {
+    if let (Some(url), None) = (ws_url, &heads) {
+        match geth.subscribe_new_heads(url) {
+            Ok(subscription) => *heads = Some(subscription),
+            Err(e) => log::warn!("newHeads subscribe failed, polling instead: {}", e),
+        }
+    }
+    if let Some(subscription) = heads {
+        log::info!(
+            "waiting for newHeads at db #{} eth #{}",
+            db_block_number,
+            last_chain_block_number
+        );
+        match subscription.next_head(POLL_INTERVAL) {
+            Ok(_) => return,
+            Err(e) => {
+                log::warn!("newHeads subscription dropped, polling instead: {}", e);
+                *heads = None;
+            }
+        }
+    }
+    log::info!(
+        "sleeping 10 sec at db #{} eth #{}",
+        db_block_number,
+        last_chain_block_number
+    );
+    shutdown::sleep(POLL_INTERVAL); // then sleep
+}
+
+// geth transport hiccups and rate limits get another try. errors from
+// outside geth (sql, block/log mismatches) are retried too.
+fn is_retryable(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<geth::Error>()
+        .is_none_or(geth::Error::is_retryable)
+}
+
+// catch up many blocks with one eth_getLogs and batched block fetches.
+// returns the last block processed.
+fn tail_range(
     geth: &geth::Client,
     db: &mut sql::Client,
+    from: u32,
+    to: u32,
+    ingest: &Ingest,
+    summary: &mut Summary,
+) -> Result<Option<InfuraBlock>, Box<dyn Error>> {
+    let blocks =
+        fetch_range(geth, from, to, &ingest.log_filter).map_err(|e| e as Box<dyn Error>)?;
+    if let Some((first, _)) = blocks.first() {
+        if is_orphaned_parent(db, first) {
+            rollback_reorg(geth, db, first)?;
+            return Ok(None);
+        }
+    }
+    let receipts = fetch_receipts(
+        geth,
+        ingest,
+        &blocks.iter().map(|(block, _)| block).collect::<Vec<_>>(),
+    )?;
+    let mut last_block = None;
+    for ((block, logs), receipts) in blocks.into_iter().zip(receipts) {
+        if shutdown::requested() {
+            break;
+        }
+        process_logs_and_mark_block(
+            &ingest.handlers,
+            db,
+            block.number,
+            logs,
+            &receipts,
+            &block,
+            summary,
+        );
+        last_block = Some(block);
+    }
+    Ok(last_block)
+}
+
+// each block's receipts when ingest.receipts is on, no receipts otherwise
+fn fetch_receipts(
+    geth: &geth::Client,
+    ingest: &Ingest,
+    blocks: &[&InfuraBlock],
+) -> geth::CallResult<Vec<Vec<TransactionReceipt>>> {
+    if !ingest.receipts {
+        return Ok(blocks.iter().map(|_| vec![]).collect());
+    }
+    geth.receipts(blocks)
+}
+
+type BlockLogs = (InfuraBlock, Vec<InfuraLog>);
+
+// blocks from..=to, each with its logs
+fn fetch_range(
+    geth: &geth::Client,
+    from: u32,
+    to: u32,
+    log_filter: &geth::LogFilter,
+) -> Result<Vec<BlockLogs>, Box<dyn Error + Send + Sync>> {
+    let blocks = geth.blocks(from, to)?;
+    // a node answering from two forks mid-batch. not a geth::Error, so retried
+    if let Some(pair) = blocks
+        .windows(2)
+        .find(|pair| pair[1].parent_hash != pair[0].hash)
+    {
+        return Err(Box::from(format!(
+            "block #{} parent {} does not match #{} hash {}",
+            pair[1].number, pair[1].parent_hash, pair[0].number, pair[0].hash
+        )));
+    }
+    let mut logs_by_block: HashMap<u32, Vec<InfuraLog>> = HashMap::new();
+    for log in geth.logs_range(from, to, log_filter)? {
+        let number = u32::from_str_radix(log.block_number.strip_prefix("0x").unwrap(), 16)?;
+        logs_by_block.entry(number).or_default().push(log);
+    }
+    blocks
+        .into_iter()
+        .map(|block| {
+            let logs = logs_by_block.remove(&block.number).unwrap_or_default();
+            if let Some(log) = logs.iter().find(|log| log.block_hash != block.hash) {
+                return Err(Box::from(format!(
+                    "block #{} hash {} does not match log block hash {}",
+                    block.number, block.hash, log.block_hash
+                )));
+            }
+            Ok((block, logs))
+        })
+        .collect()
+}
+
+// returns the block number the db was rolled back to
+fn rollback_reorg(
+    geth: &geth::Client,
+    db: &mut sql::Client,
+    block: &InfuraBlock,
+) -> Result<u32, Box<dyn Error>> {
+    log::warn!(
+        "reorg detected at #{} parent {}",
+        block.number,
+        block.parent_hash
+    );
+    let ancestor = reorg::find_common_ancestor(geth, block.number - 1, |number| {
+        InfuraBlock::db_hash(db, number)
+    })?;
+    reorg::rollback(db, ancestor);
+    Ok(ancestor)
+}
+
+// a stored parent block with a different hash means the db is on an orphaned fork
+fn is_orphaned_parent(db: &mut sql::Client, block: &InfuraBlock) -> bool {
+    match InfuraBlock::db_hash(db, block.number - 1) {
+        Some(hash) => block.parent_hash.strip_prefix("0x").unwrap() != hash,
+        None => false,
+    }
+}
+
+// what tail or backfill got committed, logged when the command exits
+#[derive(Debug, Default)]
+struct Summary {
+    last_block: Option<u32>,
+    blocks: usize,
+    logs: usize,
+    handled: BTreeMap<&'static str, usize>,
+    handler_errors: usize,
+}
+
+impl Summary {
+    fn committed(&mut self, block_number: u32, logs: usize, handled: &handlers::Handled) {
+        self.last_block = Some(block_number);
+        self.blocks += 1;
+        self.logs += logs;
+        for (name, count) in &handled.counts {
+            *self.handled.entry(name).or_default() += count;
+        }
+        self.handler_errors += handled.errors.len();
+    }
+
+    fn log(&self, command: &str) {
+        log::info!(
+            "{} summary: last committed block {}. {} blocks. {} logs. handled {}. {} handler errors",
+            command,
+            self.last_block
+                .map_or("none".to_string(), |number| format!("#{}", number)),
+            self.blocks,
+            self.logs,
+            handlers::Handled::counts_in_words(&self.handled),
+            self.handler_errors,
+        );
+    }
+}
+
+// which logs get fetched and the handlers run on them, from config
+struct Ingest<'a> {
+    log_filter: geth::LogFilter,
+    handlers: handlers::Registry<'a>,
+    // fetch and store transaction receipts too, tail.receipts
+    receipts: bool,
+}
+
+impl<'a> Ingest<'a> {
+    fn new(config: &config::LogsConfig, handlers: handlers::Registry<'a>) -> Ingest<'a> {
+        Ingest {
+            log_filter: log_filter(config, &handlers),
+            handlers,
+            receipts: false,
+        }
+    }
+}
+
+fn process_logs_and_mark_block(
+    handlers: &handlers::Registry,
+    db: &mut sql::Client,
     fetch_block_number: u32,
     logs: Vec<InfuraLog>,
+    receipts: &[TransactionReceipt],
     block: &InfuraBlock,
+    summary: &mut Summary,
 ) {
+    let started = std::time::Instant::now();
     let mut db = sql::TransactionClient::new(db);
-    match process_logs(geth, &mut db, fetch_block_number, logs) {
-        Ok(_) => {
-            // mark block as visited
-            db.q(block.to_upsert_sql());
-            db.client.commit().unwrap();
-        }
-        Err(e) => {
-            db.client.rollback().unwrap();
-            log::info!("block {} processing failed: {}", fetch_block_number, e)
-        }
+    let handled = process_logs(handlers, &mut db, fetch_block_number, &logs);
+    for transaction in &block.transactions {
+        db.q(transaction.to_upsert_sql());
     }
+    for receipt in receipts {
+        db.q(receipt.to_upsert_sql());
+    }
+    // mark block as visited
+    db.q(block.to_upsert_sql());
+    db.client.commit().unwrap();
+    metrics::BLOCK_SECONDS.observe("", started.elapsed());
+    summary.committed(fetch_block_number, logs.len(), &handled);
 }
 
 fn seconds_since_block(block: &InfuraBlock) -> u64 {
@@ -146,286 +710,406 @@ fn seconds_since_block(block: &InfuraBlock) -> u64 {
         - (block.timestamp as u64)
 }
 
-fn process_logs(
-    geth: &geth::Client,
-    db: &mut sql::TransactionClient,
-    fetch_block_number: u32,
-    logs: Vec<InfuraLog>,
-) -> Result<(), Box<dyn Error>> {
-    let mut topic_swap_count = 0;
-    let mut topic_sync_count = 0;
-    let mut topic_transfer_count = 0;
-    for log in &logs {
-        db.q(log.to_upsert_sql());
-        if log.topics.len() > 0 {
-            let _ = match log.topics[0].as_str() {
-                uniswap::v2::TOPIC_SWAP => {
-                    topic_swap_count += 1;
-                    process_swap(db, log, fetch_block_number)
-                }
-                uniswap::v2::TOPIC_SYNC => {
-                    topic_sync_count += 1;
-                    process_sync(geth, db, log, fetch_block_number)
-                }
-                erc20::TOPIC_TRANSFER => {
-                    topic_transfer_count += 1;
-                    Ok(())
-                }
-                _ => Ok(()),
-            };
-        }
+fn log_filter(config: &config::LogsConfig, handlers: &handlers::Registry) -> geth::LogFilter {
+    let mut filter = geth::LogFilter {
+        addresses: config
+            .addresses
+            .iter()
+            .map(|address| {
+                Address::from_slice(
+                    &hex::decode(address.strip_prefix("0x").unwrap_or(address)).unwrap(),
+                )
+            })
+            .collect(),
+        topics: vec![],
+    };
+    if config.topics == config::LogTopics::Handled {
+        filter.topics = handlers.topics().into_iter().map(str::to_string).collect();
     }
     log::info!(
-        "#{} {} logs. {} erc20 transfer logs. uniswap {} swaps {} syncs",
-        fetch_block_number,
-        logs.len(),
-        topic_transfer_count,
-        topic_swap_count,
-        topic_sync_count,
+        "logs: {:?} topics, {} contract addresses",
+        config.topics,
+        filter.addresses.len()
     );
-    Ok(())
+    filter
 }
 
-fn process_sync(
-    geth: &geth::Client,
+// store the raw logs and run the handlers on them. handler errors are
+// logged with the block and do not stop it from being committed, the
+// stored logs can be replayed once the handler is fixed.
+fn process_logs(
+    handlers: &handlers::Registry,
     db: &mut sql::TransactionClient,
-    log: &InfuraLog,
     fetch_block_number: u32,
-) -> Result<(), Box<dyn Error>> {
-    let pool = ensure_pool(geth, db, &log.address)?;
-    let reserves = (
-        U256::from_str_radix(&log.data[2..66], 16).unwrap(),
-        U256::from_str_radix(&log.data[66..130], 16).unwrap(),
-    );
+    logs: &[InfuraLog],
+) -> handlers::Handled {
+    for log in logs {
+        db.q(log.to_upsert_sql());
+    }
+    let handled = handlers.handle(db, logs);
     log::info!(
-        "#{} tx {:0>3} log sync( pool {} reserves {:?} )",
+        "#{} {} logs. handled {}",
         fetch_block_number,
-        log.transaction_index,
-        log.address.strip_prefix("0x").unwrap(),
-        reserves,
+        logs.len(),
+        handlers::Handled::counts_in_words(&handled.counts),
     );
-    update_pool_reserves(db, &pool, fetch_block_number, reserves)?;
-    Ok(())
+    log_handler_errors(fetch_block_number, &handled);
+    handled
 }
 
-fn process_swap(
-    db: &mut sql::TransactionClient,
-    log: &InfuraLog,
-    block_number: u32,
-) -> Result<(), Box<dyn Error>> {
-    let swap_call = SwapCall::from(log);
-    let sql = uniswap::v2::Pool::find_by_contract_address(log.address.as_str().into());
-    match db.first(sql) {
-        Some(row) => {
-            let pool = uniswap::v2::Pool::from(&row);
-            let mut in0_eth = BigInt::from(0);
-            let mut in1_eth = BigInt::from(0);
-            let sql = uniswap::v2::Reserves::find_by_pool(&pool);
-            match db.first(sql) {
-                Some(row) => {
-                    let reserves = uniswap::v2::Reserves::from_row(&row, &pool);
-                    let x = BigInt::from_str_radix(&reserves.x.to_string(), 10).unwrap();
-                    let y = BigInt::from_str_radix(&reserves.y.to_string(), 10).unwrap();
-                    if is_cash_token(pool.token0) {
-                        in0_eth = swap_call.in0.clone();
-                        in1_eth = if y > BigInt::from(0) {
-                            swap_call.in1.clone().mul(x).div(y)
-                        } else {
-                            BigInt::from(0)
-                        };
-                    } else if is_cash_token(pool.token1) {
-                        in0_eth = if x > BigInt::from(0) {
-                            swap_call.in0.clone().mul(y).div(x)
-                        } else {
-                            BigInt::from(0)
-                        };
-                        in1_eth = swap_call.in1.clone();
-                    }
-                }
-                None => log::info!(
-                    "Warning: swap recorded with no reserves available for pool {}",
-                    pool.contract_address
-                ),
-            }
-            log::info!(
-                "#{} tx {:0>3} log swap( pool {} swap in0 {} in0_eth {:?} in1 {} in1_eth {:?} out0 {} out1 {} )",
-                block_number,
-                log.transaction_index,
-                log.address.strip_prefix("0x").unwrap(),
-                swap_call.in0,
-                in0_eth,
-                swap_call.in1,
-                in1_eth,
-                swap_call.out0,
-                swap_call.out1
-            );
-            let swap = uniswap::v2::Swap {
-                pool: &pool,
-                block_number: block_number as u128,
-                transaction_index: log.transaction_index,
-                in0_eth,
-                in1_eth,
-                call_params: swap_call,
-            };
-            db.q(swap.to_upsert_sql());
-        }
-        None => {
-            log::warn!("process_swap could not find pool in db {}", log.address);
-        }
+fn log_handler_errors(block_number: u32, handled: &handlers::Handled) {
+    for error in &handled.errors {
+        log::warn!("#{} handler failed: {}", block_number, error);
     }
-    Ok(())
 }
 
-fn is_cash_token(token_address: Address) -> bool {
-    let address = format!("{:x}", token_address);
-    match address.as_str() {
-        "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2" => true, // WETH
-        _ => false,
-    }
-}
+// run handlers again over the logs table instead of eth_getLogs, after a
+// handler fix or for a new decoder. one transaction per block that also
+// moves the handler's cursor, so a stopped replay of the same --from/--to
+// resumes after the last block it committed. without --handler every
+// handler runs, cursor "all".
+fn replay(
+    handlers: handlers::Registry,
+    db: &mut sql::Client,
+    from: u32,
+    to: u32,
+    handler: Option<&str>,
+    restart: bool,
+) {
+    let names = handlers.names();
+    let (name, handlers) = match handler {
+        None => ("all", handlers),
+        Some(name) => match handlers.only(name) {
+            Some(handlers) => (name, handlers),
+            None => {
+                log::warn!(
+                    "replay: no handler {}. enabled handlers: {}",
+                    name,
+                    names.join(", ")
+                );
+                return;
+            }
+        },
+    };
+    let topics = handlers.topics();
+    let cursor = replay::Cursor::find(db, name).filter(|_| !restart);
+    let Some(start) = replay::Cursor::start(cursor.as_ref(), from, to) else {
+        log::info!(
+            "replay {} #{}-#{} already done. --restart to run it again",
+            name,
+            from,
+            to
+        );
+        return;
+    };
+    log::info!("replay {} #{}-#{} from #{}", name, from, to, start);
 
-fn ensure_pool(
-    geth: &geth::Client,
-    db: &mut sql::TransactionClient,
-    address: &str,
-) -> Result<uniswap::v2::Pool, Box<dyn Error>> {
-    let sql = uniswap::v2::Pool::find_by_contract_address(address.into());
-    match db.first(sql) {
-        Some(pool_row) => Ok(uniswap::v2::Pool::from(&pool_row)),
-        None => {
-            let abi_file = std::fs::File::open("abi/uniswap_v2_pair.json").unwrap();
-            let abi_uniswap_pair = ethabi::Contract::load(abi_file).unwrap();
-            let log_address =
-                Address::from_slice(&hex::decode(address.strip_prefix("0x").unwrap()).unwrap());
-            match create_pool(geth, db, &abi_uniswap_pair, log_address) {
-                Ok(pool) => Ok(pool),
-                Err(e) => {
-                    log::warn!("pool creation {} failed: {}", hex::encode(log_address), e);
-                    Err(Box::from(e))
-                }
+    let mut summary = Summary::default();
+    let mut chunk_from = start;
+    while chunk_from <= to && !shutdown::requested() {
+        let chunk_to = chunk_from.saturating_add(CATCHUP_RANGE - 1).min(to);
+        let rows = db.q(InfuraLog::find_by_block_range_sql(
+            chunk_from, chunk_to, &topics,
+        ));
+        let mut logs_by_block: BTreeMap<u32, Vec<InfuraLog>> = BTreeMap::new();
+        for row in &rows {
+            let number = row.get::<&str, i32>("block_number") as u32;
+            logs_by_block
+                .entry(number)
+                .or_default()
+                .push(InfuraLog::from(row));
+        }
+        for (block_number, logs) in logs_by_block {
+            if shutdown::requested() {
+                break;
             }
+            let mut db = sql::TransactionClient::new(db);
+            handlers.reset_block(&mut db, block_number);
+            let handled = handlers.handle(&mut db, &logs);
+            log_handler_errors(block_number, &handled);
+            let cursor = replay::Cursor {
+                handler: name.to_string(),
+                block_number,
+                from_block: Some(from),
+                to_block: Some(to),
+            };
+            db.q(cursor.to_upsert_sql());
+            db.client.commit().unwrap();
+            summary.committed(block_number, logs.len(), &handled);
+        }
+        if !shutdown::requested() {
+            let cursor = replay::Cursor {
+                handler: name.to_string(),
+                block_number: chunk_to,
+                from_block: Some(from),
+                to_block: Some(to),
+            };
+            db.q(cursor.to_upsert_sql());
+            log::info!("replay {} #{} done", name, chunk_to);
         }
+        chunk_from = chunk_to + 1;
     }
+    summary.log("replay");
 }
 
 fn refresh(geth: &geth::Client, db: &mut sql::Client, eth_block: u32) {
     let sql = uniswap::v2::Pool::all();
     let rows = db.q(sql);
     let rows_count = rows.len();
-    for (idx, row) in rows.iter().enumerate() {
-        let pool = uniswap::v2::Pool::from(row);
-        log::info!("refresh: {}/{} {:?}", idx, rows_count, pool);
-        let abi_file = std::fs::File::open("abi/uniswap_v2_pair.json").unwrap();
-        let abi_pool = ethabi::Contract::load(abi_file).unwrap();
-        let reserves =
-            uniswap::v2::Pool::reserves(&geth, &abi_pool, &pool.contract_address, eth_block)
-                .unwrap();
-        let mut db = sql::TransactionClient::new(db);
-        match update_pool_reserves(&mut db, &pool, eth_block, reserves) {
-            Ok(_) => {
-                db.client.commit().unwrap();
-            }
+    let abi_file = std::fs::File::open("abi/uniswap_v2_pair.json").unwrap();
+    let abi_pool = ethabi::Contract::load(abi_file).unwrap();
+    let (mut updated, mut failed) = (0, 0);
+    'chunks: for (chunk_idx, chunk) in rows.chunks(multicall::CHUNK_SIZE).enumerate() {
+        let pools = chunk
+            .iter()
+            .map(uniswap::v2::Pool::from)
+            .collect::<Vec<_>>();
+        let addresses = pools
+            .iter()
+            .map(|pool| pool.contract_address)
+            .collect::<Vec<_>>();
+        log::info!(
+            "refresh: {}/{} {} pools",
+            chunk_idx * multicall::CHUNK_SIZE,
+            rows_count,
+            pools.len()
+        );
+        let all_reserves = match uniswap::v2::Pool::reserves(geth, &abi_pool, &addresses, eth_block)
+        {
+            Ok(all_reserves) => all_reserves,
             Err(err) => {
-                db.client.rollback().unwrap();
-                log::info!("warning: pool reserves update failed. {}", err)
+                log::warn!(
+                    "refresh: reserves of {} pools failed, skipped: {}",
+                    pools.len(),
+                    err
+                );
+                failed += pools.len();
+                continue;
             }
         };
-    }
-}
-
-fn discover(geth: &geth::Client, db: &mut sql::Client) {
-    uniswap::v2::Factory::setup();
-    let pool_count = uniswap::v2::Factory::pool_count(&geth).unwrap().low_u64();
-    log::info!("Uniswap v2 contract count {:?}", pool_count,);
-    let abi_file = std::fs::File::open("abi/uniswap_v2_pair.json").unwrap();
-    let abi_pool = ethabi::Contract::load(abi_file).unwrap();
-    for pool_idx in pool_count - 10..pool_count {
-        let address = uniswap::v2::Factory::pool_addr(&geth, pool_idx).unwrap();
-        let mut db = sql::TransactionClient::new(db);
-        match create_pool(geth, &mut db, &abi_pool, address) {
-            Ok(_) => (),
-            Err(err) => log::info!(
-                "warning: pool creation {} failed: {}",
-                hex::encode(address),
-                err
-            ),
+        for (pool, reserves) in pools.iter().zip(all_reserves) {
+            if shutdown::requested() {
+                break 'chunks;
+            }
+            let reserves = match reserves {
+                Ok(reserves) => reserves,
+                Err(err) => {
+                    log::info!("warning: pool {:?} reserves fetch failed. {}", pool, err);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let mut db = sql::TransactionClient::new(db);
+            match handlers::update_pool_reserves(&mut db, pool, eth_block, reserves) {
+                Ok(_) => {
+                    db.client.commit().unwrap();
+                    updated += 1;
+                }
+                Err(err) => {
+                    db.client.rollback().unwrap();
+                    failed += 1;
+                    log::info!("warning: pool reserves update failed. {}", err)
+                }
+            };
         }
     }
+    log::info!(
+        "refresh summary{}: block #{}. {}/{} pools updated, {} failed",
+        if shutdown::requested() {
+            " (shutdown)"
+        } else {
+            ""
+        },
+        eth_block,
+        updated,
+        rows_count,
+        failed
+    );
 }
 
-fn create_pool(
-    geth: &geth::Client,
-    db: &mut sql::TransactionClient,
-    abi_pool: &ethabi::Contract,
-    address: Address,
-) -> Result<uniswap::v2::Pool, Box<dyn Error>> {
-    let tokens = crate::uniswap::v2::Pool::tokens(&geth, &abi_pool, &address)?;
-    let pool = uniswap::v2::Pool {
-        contract_address: address,
-        token0: tokens.0,
-        token1: tokens.1,
-    };
-    create_token(&geth, db, tokens.0)?;
-    create_token(&geth, db, tokens.1)?;
+// allPairs indexes discover visits per eth_call batch
+const DISCOVER_CHUNK: u64 = 100;
 
-    log::info!("Created {:?}", pool);
-    db.q(pool.to_upsert_sql());
-    Ok(pool)
+// allPairs indexes to look at: --from-index..=--to-index, by default every pair
+fn discover_range(
+    pool_count: u64,
+    from_index: Option<u64>,
+    to_index: Option<u64>,
+) -> std::ops::Range<u64> {
+    let from = from_index.unwrap_or(0);
+    let to = to_index.map_or(pool_count, |to| (to + 1).min(pool_count));
+    from..to.max(from)
 }
 
-fn update_pool_reserves<'a>(
-    db: &mut sql::TransactionClient,
-    pool: &'a uniswap::v2::Pool,
-    eth_block: u32,
-    reserves: (U256, U256),
-) -> Result<uniswap::v2::Reserves<'a>, Box<dyn Error>> {
-    let pool_reserves = uniswap::v2::Reserves::new(&pool, eth_block, reserves);
-    db.q(pool_reserves.to_upsert_sql());
-    Ok(pool_reserves)
+// the indexes in range with no pool stored yet. failed pairs stay missing
+// until a run stores them, and pairs PairCreated stored are skipped without
+// hiding the ones before them. stored is in order.
+fn missing_indexes(range: std::ops::Range<u64>, stored: &[u64]) -> Vec<u64> {
+    range
+        .filter(|index| stored.binary_search(index).is_err())
+        .collect()
 }
 
-fn create_token(
-    geth: &crate::geth::Client,
-    db: &mut sql::TransactionClient,
-    address: Address,
-) -> Result<Coin, Box<dyn Error>> {
-    let exist = sql_query_builder::Select::new()
-        .select("*")
-        .from("coins")
-        .where_clause("contract_address = $1");
-    let rows = db.q((exist.to_string(), vec![Box::new(format!("{:x}", address))]));
-    if rows.len() == 0 {
-        let token = Erc20 { address };
-        let mut name = token.name(&geth).unwrap_or_else(|e| {
-            log::info!("warning: token decode fail: {:?}", e);
-            "".to_string()
-        });
-        string_filter_null(&mut name); // psql does not allow nulls
-        let mut symbol = token.symbol(&geth).unwrap_or_else(|e| {
-            log::info!("warning: symbol decode fail: {:?}", e);
+// store factory pairs with their allPairs index, one transaction per pool so
+// an interrupted run resumes with the pairs it did not store. pair tokens and
+// coin metadata are read a chunk at a time
+fn discover(
+    geth: &geth::Client,
+    db: &mut sql::Client,
+    from_index: Option<u64>,
+    to_index: Option<u64>,
+) {
+    uniswap::v2::Factory::setup();
+    let pool_count = match uniswap::v2::Factory::pool_count(geth) {
+        Ok(count) => count.low_u64(),
+        Err(err) => {
+            log::warn!("discover: allPairsLength failed: {}", err);
+            return;
+        }
+    };
+    let range = discover_range(pool_count, from_index, to_index);
+    let stored = uniswap::v2::Factory::sql_stored_indexes(db, &range);
+    let missing = missing_indexes(range.clone(), &stored);
+    log::info!(
+        "discover: factory has {} pairs. #{}-#{}: {} stored, visiting {}",
+        pool_count,
+        range.start,
+        range.end.saturating_sub(1),
+        stored.len(),
+        missing.len()
+    );
+    let abi_file = std::fs::File::open("abi/uniswap_v2_pair.json").unwrap();
+    let abi_pool = ethabi::Contract::load(abi_file).unwrap();
+    let started = std::time::Instant::now();
+    let (mut visited, mut created, mut failed) = (0, 0, vec![]);
+    for chunk in missing.chunks(DISCOVER_CHUNK as usize) {
+        if shutdown::requested() {
+            break;
+        }
+        let addresses = match uniswap::v2::Factory::pool_addrs(geth, chunk) {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                log::warn!(
+                    "discover: allPairs #{}-#{} failed: {}",
+                    chunk[0],
+                    chunk[chunk.len() - 1],
+                    err
+                );
+                break;
+            }
+        };
+        let pools = chunk
+            .iter()
+            .map(|index| *index as u32)
+            .zip(addresses)
+            .collect::<Vec<_>>();
+        let results = match handlers::create_pools(geth, db, &abi_pool, &pools) {
+            Ok(results) => results,
+            Err(err) => {
+                log::warn!(
+                    "discover: pools #{}-#{} failed: {}",
+                    chunk[0],
+                    chunk[chunk.len() - 1],
+                    err
+                );
+                break;
+            }
+        };
+        for ((index, address), result) in pools.iter().zip(results) {
+            match result {
+                Ok(_) => created += 1,
+                Err(err) => {
+                    failed.push(*index);
+                    log::warn!(
+                        "discover: pool #{} {} failed: {}",
+                        index,
+                        hex::encode(address),
+                        err
+                    )
+                }
+            }
+            visited += 1;
+        }
+        let per_sec = visited as f32 / started.elapsed().as_secs_f32();
+        log::info!(
+            "discover #{} done. {}/{} pairs. {} created, {} failed. {:.1} pairs/sec, {} left",
+            chunk[chunk.len() - 1],
+            visited,
+            missing.len(),
+            created,
+            failed.len(),
+            per_sec,
+            elapsed_in_words(((missing.len() - visited) as f32 / per_sec.max(0.001)) as u64)
+        );
+    }
+    log::info!(
+        "discover summary{}: {}/{} missing pairs visited. {} pools created, {} failed{}",
+        if shutdown::requested() {
+            " (shutdown)"
+        } else {
+            ""
+        },
+        visited,
+        missing.len(),
+        created,
+        failed.len(),
+        if failed.is_empty() {
             "".to_string()
-        });
-        string_filter_null(&mut symbol);
-        if let Ok(decimals) = token.decimals(&geth) {
-            let coin = Coin {
-                contract_address: token.address,
-                name,
-                symbol,
-                decimals,
-            };
-            db.q(coin.to_upsert_sql());
-            log::info!("Created {:?}", coin);
-            Ok(coin)
         } else {
-            Err(Box::from(format!("coin decimals() failed for {}", address)))
+            format!(
+                ": {}",
+                failed
+                    .iter()
+                    .map(|index| format!("#{}", index))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
         }
-    } else {
-        Ok(Coin::from(&rows[0]))
+    );
+    if visited < missing.len() || !failed.is_empty() {
+        log::info!("discover: run discover again to visit the pairs not stored");
     }
 }
 
-fn string_filter_null(str: &mut String) {
-    str.retain(|c| c != '\0')
+// a pool's decimal-adjusted spot price at block_number, from its last Sync
+fn price(db: &mut sql::Client, address: &str, block_number: u32) {
+    let address = address.strip_prefix("0x").unwrap_or(address).to_lowercase();
+    let Some(pool) = db.q_last(uniswap::v2::Pool::find_by_contract_address(
+        uniswap::v2::AddressStringNox(address.clone()),
+    )) else {
+        log::info!("price: no pool {}", address);
+        return;
+    };
+    let pool = uniswap::v2::Pool::from(&pool);
+    let Some(price) = db
+        .q_last(pool.price_at(block_number))
+        .and_then(|row| price::Price::from_row(&row))
+    else {
+        log::info!(
+            "price: pool {} has no reserves and coin decimals at or before #{}",
+            address,
+            block_number
+        );
+        return;
+    };
+    let symbol = |db: &mut sql::Client, token: &Address| {
+        db.q_last(coin::Coin::find_by_contract_address(token.into()))
+            .map(|row| coin::Coin::from(&row).symbol)
+            .unwrap_or_else(|| format!("{:x}", token))
+    };
+    let (symbol0, symbol1) = (symbol(db, &pool.token0), symbol(db, &pool.token1));
+    log::info!(
+        "price {} #{} (reserves of #{}): 1 {} = {} {}. 1 {} = {} {}",
+        address,
+        block_number,
+        price.block_number,
+        symbol0,
+        price::decimal(&price.price0, 18),
+        symbol1,
+        symbol1,
+        price::decimal(&price.price1, 18),
+        symbol0
+    );
 }
 
 fn elapsed_in_words(secs: u64) -> String {
@@ -434,51 +1118,315 @@ fn elapsed_in_words(secs: u64) -> String {
     if secs > 60 * 60 * 24 {
         let days = secs / 60 / 60 / 24;
         msg.push_str(&format!("{} days ", days));
-        secs = secs - days * 60 * 60 * 24;
+        secs -= days * 60 * 60 * 24;
     }
     if secs > 60 * 60 {
         let hours = secs / 60 / 60;
         msg.push_str(&format!("{} hours ", hours));
-        secs = secs - hours * 60 * 60;
+        secs -= hours * 60 * 60;
     }
     if secs > 60 {
         let mins = secs / 60;
         msg.push_str(&format!("{} mins ", mins));
-        secs = secs - mins * 60;
+        secs -= mins * 60;
     }
     msg.push_str(&format!("{} secs", secs));
-    return msg;
+    msg
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::uniswap::v2::{Pool, Reserves};
-
     use super::*;
 
     #[test]
-    fn test_token0_to_token0_eth() {
-        let pool = Pool {
-            contract_address: [0; 20].into(),
-            token0: [0; 20].into(),
-            token1: [0; 20].into(),
+    fn test_discover_range() {
+        // every pair
+        assert_eq!(discover_range(500, None, None), 0..500);
+        // --from-index/--to-index are inclusive
+        assert_eq!(discover_range(500, Some(10), Some(19)), 10..20);
+        assert_eq!(discover_range(500, None, Some(9999)), 0..500);
+        assert_eq!(discover_range(500, Some(300), Some(100)), 300..300);
+    }
+
+    #[test]
+    fn test_missing_indexes() {
+        assert_eq!(missing_indexes(0..5, &[]), vec![0, 1, 2, 3, 4]);
+        // #2 failed last run, #4 came from a PairCreated
+        assert_eq!(missing_indexes(0..6, &[0, 1, 3, 4]), vec![2, 5]);
+        assert!(missing_indexes(0..3, &[0, 1, 2]).is_empty());
+    }
+
+    // uniswap v2 factory with pair_count pairs, pair #n at 0x1000 + n, all of
+    // tokens 0xa and 0xb. token0 of the pairs in broken reverts. allPairs
+    // indexes asked for go to asked, in the fake's order. multicalls counts
+    // aggregate3 requests.
+    fn fake_factory(
+        pair_count: u64,
+        broken: std::sync::Arc<std::sync::Mutex<Vec<u64>>>,
+        asked: std::sync::Arc<std::sync::Mutex<Vec<u64>>>,
+        multicalls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    ) -> geth::Client {
+        use ethabi::{Contract, Token};
+        uniswap::v2::Factory::setup();
+        erc20::ABI.get_or_init(|| {
+            Contract::load(std::fs::File::open("abi/ERC20.json").unwrap()).unwrap()
+        });
+        let abi = |file: &str| Contract::load(std::fs::File::open(file).unwrap()).unwrap();
+        let (pair, multicall) = (abi("abi/uniswap_v2_pair.json"), abi("abi/multicall3.json"));
+        let factory = uniswap::v2::ABI.get().unwrap().clone();
+        let selector = |abi: &Contract, name: &str| abi.function(name).unwrap().short_signature();
+        let pair_address = |index: u64| Address::from_low_u64_be(0x1000 + index);
+        geth::Client::build(&[geth::fake::serve(move |request| {
+            let data = request["params"][0]["data"].as_str().unwrap();
+            let data = hex::decode(&data[2..]).unwrap();
+            let (call, input) = data.split_at(4);
+            let output = if call == selector(&factory, "allPairsLength") {
+                vec![Token::Uint(pair_count.into())]
+            } else if call == selector(&factory, "allPairs") {
+                let index = factory.function("allPairs").unwrap().decode_input(input);
+                let index = index.unwrap()[0].clone().into_uint().unwrap().low_u64();
+                asked.lock().unwrap().push(index);
+                vec![Token::Address(pair_address(index))]
+            } else {
+                multicalls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let call3s = multicall
+                    .function("aggregate3")
+                    .unwrap()
+                    .decode_input(input);
+                let Token::Array(call3s) = call3s.unwrap().remove(0) else {
+                    unreachable!()
+                };
+                let results = call3s
+                    .into_iter()
+                    .map(|call3| {
+                        let fields = call3.into_tuple().unwrap();
+                        let to = fields[0].clone().into_address().unwrap();
+                        let call = fields[2].clone().into_bytes().unwrap();
+                        let call = &call[..4];
+                        let broken = broken.lock().unwrap();
+                        let output = if call == selector(&pair, "token0") {
+                            let index = to.to_low_u64_be() - 0x1000;
+                            (!broken.contains(&index))
+                                .then(|| Token::Address(Address::from_low_u64_be(0xa)))
+                        } else if call == selector(&pair, "token1") {
+                            Some(Token::Address(Address::from_low_u64_be(0xb)))
+                        } else if call == selector(erc20::ABI.get().unwrap(), "decimals") {
+                            Some(Token::Uint(18.into()))
+                        } else {
+                            Some(Token::String("T".to_string()))
+                        };
+                        Token::Tuple(vec![
+                            Token::Bool(output.is_some()),
+                            Token::Bytes(output.map_or(vec![], |output| ethabi::encode(&[output]))),
+                        ])
+                    })
+                    .collect();
+                vec![Token::Array(results)]
+            };
+            serde_json::json!({ "result": format!("0x{}", hex::encode(ethabi::encode(&output))) })
+        })])
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_discover_after_pair_created() {
+        let mut db = sql::test_schema_client("test_discover_after_pair_created");
+        let broken = std::sync::Arc::new(std::sync::Mutex::new(vec![7]));
+        let asked = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let multicalls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let geth = fake_factory(25, broken.clone(), asked.clone(), multicalls.clone());
+        let multicalls = || multicalls.swap(0, std::sync::atomic::Ordering::SeqCst);
+        let stored = |db: &mut sql::Client| uniswap::v2::Factory::sql_stored_indexes(db, &(0..25));
+
+        // tail stored pair #11 from its PairCreated before any discover
+        let registry = handlers::Registry::from_config(
+            &geth,
+            &config::HandlersConfig {
+                enabled: vec!["pair_created".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let topic = |address: u64| format!("0x{:064x}", address);
+        let pair_created = InfuraLog {
+            address: format!("0x{}", uniswap::v2::UNISWAP_FACTORY),
+            block_number: "0x1".to_string(),
+            topics: vec![
+                uniswap::v2::TOPIC_PAIR_CREATED.to_string(),
+                topic(0xa),
+                topic(0xb),
+            ],
+            data: format!("0x{:064x}{:064x}", 0x1000 + 11, 12),
+            ..Default::default()
         };
-        let reserves = Reserves {
-            pool: &pool,
-            block_number: 1,
-            x: U256::from_str_radix("33044264430781", 10).unwrap(),
-            y: U256::from_str_radix("16632437277688007258761", 10).unwrap(),
+        {
+            let mut db = sql::TransactionClient::new(&mut db);
+            let handled = registry.handle(&mut db, &[pair_created]);
+            assert!(handled.errors.is_empty(), "{:?}", handled.errors);
+            db.client.commit().unwrap();
+        }
+        assert_eq!(stored(&mut db), vec![11]);
+
+        // every pair but #11, #7 fails. tokens of all 24 in one multicall,
+        // 0xa and 0xb are stored already
+        multicalls();
+        discover(&geth, &mut db, None, None);
+        assert_eq!(multicalls(), 1);
+        let all = (0..25).collect::<Vec<_>>();
+        let mut expected = all.clone();
+        expected.retain(|index| *index != 11);
+        asked.lock().unwrap().sort();
+        assert_eq!(*asked.lock().unwrap(), expected);
+        expected.retain(|index| *index != 7);
+        expected.push(11);
+        expected.sort();
+        assert_eq!(stored(&mut db), expected);
+
+        // the next run retries #7 only
+        broken.lock().unwrap().clear();
+        asked.lock().unwrap().clear();
+        discover(&geth, &mut db, None, None);
+        assert_eq!(*asked.lock().unwrap(), vec![7]);
+        assert_eq!(stored(&mut db), all);
+
+        db.client
+            .batch_execute("DROP SCHEMA test_discover_after_pair_created CASCADE")
+            .unwrap();
+    }
+
+    // scripted chain, one log per block. fork(n) names the fork block n is
+    // served from and the fork its parent is on. hashes are 0x{n}{fork}
+    fn fake_chain<F>(fork: F) -> geth::Client
+    where
+        F: Fn(u32) -> (char, char) + Send + 'static,
+    {
+        let hash = |number: u32, fork: char| format!("0x{:063x}{}", number, fork);
+        let param = |request: &serde_json::Value, index: usize, key: &str| {
+            let value = &request["params"][index];
+            let value = if key.is_empty() { value } else { &value[key] };
+            u32::from_str_radix(&value.as_str().unwrap()[2..], 16).unwrap()
+        };
+        geth::Client::build(&[geth::fake::serve(move |request| {
+            match request["method"].as_str().unwrap() {
+                "eth_getBlockByNumber" => {
+                    let number = param(request, 0, "");
+                    let (block_fork, parent_fork) = fork(number);
+                    serde_json::json!({ "result": {
+                        "hash": hash(number, block_fork),
+                        "parentHash": hash(number - 1, parent_fork),
+                        "number": format!("0x{:x}", number),
+                        "timestamp": "0x0",
+                        "transactions": [],
+                    }})
+                }
+                "eth_getLogs" => {
+                    let logs = (param(request, 0, "fromBlock")..=param(request, 0, "toBlock"))
+                        .map(|number| {
+                            serde_json::json!({
+                                "address": format!("0x{:040x}", 1),
+                                "blockHash": hash(number, fork(number).0),
+                                "blockNumber": format!("0x{:x}", number),
+                                "data": "0x",
+                                "topics": [format!("0x{:064x}", 2)],
+                                "transactionHash": format!("0x{:064x}", number),
+                                "transactionIndex": "0x0",
+                                "logIndex": "0x0",
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    serde_json::json!({ "result": logs })
+                }
+                method => panic!("unexpected {}", method),
+            }
+        })])
+    }
+
+    #[test]
+    fn test_fetch_range_parent_hashes() {
+        let geth = fake_chain(|_| ('a', 'a'));
+        let blocks = fetch_range(&geth, 10, 12, &geth::LogFilter::default()).unwrap();
+        assert_eq!(blocks.len(), 3);
+
+        // #11 answered from fork b: its parent is not the #10 we got
+        let geth = fake_chain(|number| if number == 11 { ('b', 'b') } else { ('a', 'a') });
+        let err = fetch_range(&geth, 10, 12, &geth::LogFilter::default()).unwrap_err();
+        assert!(err.to_string().contains("block #11 parent"), "{}", err);
+        assert!(is_retryable(err.as_ref() as &(dyn Error + 'static)));
+    }
+
+    #[test]
+    #[ignore = "needs postgres: make test-db"]
+    fn test_tail_range_fork() {
+        let mut db = sql::test_schema_client("test_tail_range_fork");
+        // fork b replaces #13 on, branching off #12
+        let forked = std::sync::Arc::new(AtomicBool::new(false));
+        let geth = fake_chain({
+            let forked = forked.clone();
+            move |number| match (forked.load(Ordering::SeqCst), number) {
+                (true, 13) => ('b', 'a'),
+                (true, 14..) => ('b', 'b'),
+                _ => ('a', 'a'),
+            }
+        });
+        let registry = handlers::Registry::from_config(
+            &geth,
+            &config::HandlersConfig {
+                enabled: vec![],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let ingest = Ingest::new(&config::LogsConfig::default(), registry);
+        let mut summary = Summary::default();
+        let stored = |db: &mut sql::Client| {
+            db.q((
+                "SELECT b.number, b.hash, count(l.block_number) AS logs FROM blocks b \
+                 LEFT JOIN logs l ON l.block_number = b.number AND l.block_hash = b.hash \
+                 GROUP BY b.number, b.hash ORDER BY b.number"
+                    .to_string(),
+                vec![],
+            ))
+            .iter()
+            .map(|row| {
+                let hash = row.get::<_, String>("hash");
+                assert_eq!(row.get::<_, i64>("logs"), 1);
+                (row.get::<_, i32>("number"), hash.chars().last().unwrap())
+            })
+            .collect::<Vec<_>>()
         };
 
-        let x = BigInt::from_str_radix(&reserves.x.to_string(), 10).unwrap();
-        let y = BigInt::from_str_radix(&reserves.y.to_string(), 10).unwrap();
+        let last = tail_range(&geth, &mut db, 10, 14, &ingest, &mut summary).unwrap();
+        assert_eq!(last.unwrap().number, 14);
+        let fork_a = (10..=14).map(|number| (number, 'a')).collect::<Vec<_>>();
+        assert_eq!(stored(&mut db), fork_a);
 
-        let in0 = BigInt::from_str_radix("1200000000", 10).unwrap();
-        let in0_eth = in0.mul(y).div(x);
+        // #15's parent is fork b's #14: roll back to #12, ingest nothing
+        forked.store(true, Ordering::SeqCst);
+        let last = tail_range(&geth, &mut db, 15, 16, &ingest, &mut summary).unwrap();
+        assert!(last.is_none());
+        assert_eq!(stored(&mut db), fork_a[..3].to_vec());
+        let logs = db.q(("SELECT count(*) FROM logs".to_string(), vec![]));
+        assert_eq!(logs[0].get::<_, i64>(0), 3);
 
+        // tail carries on from the ancestor, now on fork b
+        let last = tail_range(&geth, &mut db, 13, 16, &ingest, &mut summary).unwrap();
+        assert_eq!(last.unwrap().number, 16);
+        let mut expected = fork_a[..3].to_vec();
+        expected.extend((13..=16).map(|number| (number, 'b')));
+        assert_eq!(stored(&mut db), expected);
+
+        db.client
+            .batch_execute("DROP SCHEMA test_tail_range_fork CASCADE")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_backfill_ranges() {
         assert_eq!(
-            in0_eth,
-            BigInt::from_str_radix("604005720116248332", 10).unwrap()
-        )
+            backfill_ranges(10, 34, 10),
+            vec![(10, 19), (20, 29), (30, 34)]
+        );
+        assert_eq!(backfill_ranges(10, 10, 100), vec![(10, 10)]);
+        assert!(backfill_ranges(11, 10, 100).is_empty());
     }
 }
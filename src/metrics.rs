@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// a client that connects and sends nothing gives up its thread after this
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub static CHAIN_HEAD: Gauge = Gauge::new("poolpoll_chain_head_block", "latest chain block");
+pub static DB_HEAD: Gauge = Gauge::new("poolpoll_db_head_block", "latest committed block");
+pub static LAG_BLOCKS: Gauge = Gauge::new("poolpoll_lag_blocks", "chain head - db head");
+pub static LAG_SECONDS: Gauge = Gauge::new(
+    "poolpoll_lag_seconds",
+    "seconds since the latest committed block was mined",
+);
+pub static BLOCK_SECONDS: Histogram = Histogram::new(
+    "poolpoll_block_processing_seconds",
+    "store, handle and commit time per block",
+    None,
+);
+pub static RPC_SECONDS: Histogram = Histogram::new(
+    "poolpoll_rpc_request_seconds",
+    "json-rpc http request time",
+    Some("method"),
+);
+pub static RPC_ERRORS: Counter = Counter::new(
+    "poolpoll_rpc_errors_total",
+    "failed json-rpc requests, retries included",
+    Some("method"),
+);
+pub static SQL_QUERIES: Counter = Counter::new("poolpoll_sql_queries_total", "sql queries", None);
+pub static HANDLED: Counter = Counter::new(
+    "poolpoll_handled_logs_total",
+    "logs each event handler handled",
+    Some("handler"),
+);
+pub static HANDLER_ERRORS: Counter = Counter::new(
+    "poolpoll_handler_errors_total",
+    "failed event handler calls",
+    Some("handler"),
+);
+
+// upper bounds in seconds for the histograms
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    // f64 bits
+    value: AtomicU64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Gauge {
+        Gauge {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: f64) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "gauge");
+        let value = f64::from_bits(self.value.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{} {}", self.name, value);
+    }
+}
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    label: Option<&'static str>,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Counter {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        label: Option<&'static str>,
+    ) -> Counter {
+        Counter {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.inc_by("", 1);
+    }
+
+    // label is ignored for counters without one
+    pub fn inc_by(&self, label: &str, count: u64) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .entry(label.to_string())
+            .or_default() += count;
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, label: &str) -> u64 {
+        self.values
+            .lock()
+            .unwrap()
+            .get(label)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        let values = self.values.lock().unwrap();
+        if values.is_empty() && self.label.is_none() {
+            let _ = writeln!(out, "{} 0", self.name);
+        }
+        for (label, count) in values.iter() {
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                self.name,
+                labels(self.label, label, None),
+                count
+            );
+        }
+    }
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    label: Option<&'static str>,
+    values: Mutex<BTreeMap<String, Observed>>,
+}
+
+// one label's observations
+#[derive(Default)]
+struct Observed {
+    // observations <= each of BUCKETS
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        label: Option<&'static str>,
+    ) -> Histogram {
+        Histogram {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, label: &str, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let mut values = self.values.lock().unwrap();
+        let observed = values.entry(label.to_string()).or_default();
+        for (bucket, le) in observed.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        observed.count += 1;
+        observed.sum += secs;
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "histogram");
+        for (label, observed) in self.values.lock().unwrap().iter() {
+            for (bucket, le) in observed.buckets.iter().zip(BUCKETS) {
+                let le = le.to_string();
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    self.name,
+                    labels(self.label, label, Some(&le)),
+                    bucket
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{} {}",
+                self.name,
+                labels(self.label, label, Some("+Inf")),
+                observed.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{} {}",
+                self.name,
+                labels(self.label, label, None),
+                observed.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{} {}",
+                self.name,
+                labels(self.label, label, None),
+                observed.count
+            );
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// {method="eth_call",le="0.5"}
+fn labels(name: Option<&str>, value: &str, le: Option<&str>) -> String {
+    let mut pairs = vec![];
+    if let Some(name) = name {
+        pairs.push(format!("{}=\"{}\"", name, value.replace('"', "\\\"")));
+    }
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        "".to_string()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+// prometheus text format
+pub fn render() -> String {
+    let mut out = String::new();
+    for gauge in [&CHAIN_HEAD, &DB_HEAD, &LAG_BLOCKS, &LAG_SECONDS] {
+        gauge.render(&mut out);
+    }
+    for histogram in [&BLOCK_SECONDS, &RPC_SECONDS] {
+        histogram.render(&mut out);
+    }
+    for counter in [&RPC_ERRORS, &SQL_QUERIES, &HANDLED, &HANDLER_ERRORS] {
+        counter.render(&mut out);
+    }
+    out
+}
+
+// answer GET /metrics on listen from a background thread, one thread per
+// connection so a slow client does not hold up the others
+pub fn serve(listen: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    log::info!("metrics on http://{}/metrics", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                if let Err(e) = respond(stream) {
+                    log::warn!("metrics request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers, requests to /metrics have no body
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+        line.clear();
+    }
+    let (status, body) = if request_line.starts_with("GET /metrics ") {
+        ("200 OK", render())
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counter_and_histogram() {
+        let counter = Counter::new("rpc_errors_total", "errors", Some("method"));
+        counter.inc_by("eth_call", 2);
+        let histogram = Histogram::new("rpc_seconds", "latency", Some("method"));
+        histogram.observe("eth_call", Duration::from_millis(30));
+        histogram.observe("eth_call", Duration::from_millis(300));
+
+        let mut out = String::new();
+        counter.render(&mut out);
+        histogram.render(&mut out);
+        assert!(out.contains("rpc_errors_total{method=\"eth_call\"} 2\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"eth_call\",le=\"0.05\"} 1\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"eth_call\",le=\"0.5\"} 2\n"));
+        assert!(out.contains("rpc_seconds_bucket{method=\"eth_call\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("rpc_seconds_count{method=\"eth_call\"} 2\n"));
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        serve(&addr.to_string()).unwrap();
+        CHAIN_HEAD.set(17_000_000.0);
+        // connected and silent, must not block the next request
+        let _idle = TcpStream::connect(addr).unwrap();
+
+        let body = ureq::get(&format!("http://{}/metrics", addr))
+            .call()
+            .unwrap()
+            .into_string()
+            .unwrap();
+        assert!(body.contains("# TYPE poolpoll_chain_head_block gauge\n"));
+        assert!(body.contains("poolpoll_chain_head_block 17000000\n"));
+        assert!(body.contains("poolpoll_sql_queries_total "));
+        assert!(matches!(
+            ureq::get(&format!("http://{}/", addr)).call(),
+            Err(ureq::Error::Status(404, _))
+        ));
+    }
+}
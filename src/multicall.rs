@@ -0,0 +1,150 @@
+use crate::geth::{CallResult, Client, Error, ErrorDetailRpc, EthCall};
+use ethabi::token::Token;
+use ethabi::Contract;
+use ethereum_types::Address;
+use std::sync::OnceLock;
+
+// Multicall3, same address on mainnet and most evm chains. deployed at
+// block 14353601, calls pinned earlier than that will fail.
+const MULTICALL3: &str = "ca11bde05977b3631167028862be2a173976ca11";
+// calls per aggregate3. keeps each eth_call well under the node's gas cap.
+pub const CHUNK_SIZE: usize = 500;
+
+static ABI: OnceLock<Contract> = OnceLock::new();
+
+fn abi() -> &'static Contract {
+    ABI.get_or_init(|| {
+        let abi_file = std::fs::File::open("abi/multicall3.json").unwrap();
+        Contract::load(abi_file).unwrap()
+    })
+}
+
+// many contract calls through Multicall3.aggregate3, all at the same block.
+// chunks go out as one batched request. every call may fail on its own, a
+// revert comes back as a json-rpc style code 3 error for that call only.
+pub fn aggregate3(
+    geth: &Client,
+    calls: &[EthCall],
+    block_number: Option<u32>,
+) -> CallResult<Vec<CallResult<Vec<Token>>>> {
+    let multicall3 = Address::from_slice(&hex::decode(MULTICALL3).unwrap());
+    let aggregates = calls
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| {
+            let call3s = chunk
+                .iter()
+                .map(|call| {
+                    Ok(Token::Tuple(vec![
+                        Token::Address(call.to),
+                        Token::Bool(true),
+                        Token::Bytes(call.encode()?),
+                    ]))
+                })
+                .collect::<CallResult<Vec<_>>>()?;
+            Ok(EthCall {
+                to: multicall3,
+                abi: abi(),
+                function_name: "aggregate3",
+                function_params: vec![Token::Array(call3s)],
+            })
+        })
+        .collect::<CallResult<Vec<_>>>()?;
+
+    let mut results = vec![];
+    for (chunk, aggregate) in calls
+        .chunks(CHUNK_SIZE)
+        .zip(geth.eth_call_batch(&aggregates, block_number)?)
+    {
+        let return_data = match aggregate?.into_iter().next() {
+            Some(Token::Array(return_data)) if return_data.len() == chunk.len() => return_data,
+            other => {
+                return Err(Error::UnexpectedShape(format!(
+                    "aggregate3 of {} calls => {:?}",
+                    chunk.len(),
+                    other
+                )))
+            }
+        };
+        for (call, result) in chunk.iter().zip(return_data) {
+            results.push(decode_result(call, result));
+        }
+    }
+    Ok(results)
+}
+
+fn decode_result(call: &EthCall, result: Token) -> CallResult<Vec<Token>> {
+    match result {
+        Token::Tuple(fields) => match fields.as_slice() {
+            [Token::Bool(true), Token::Bytes(output)] => call.decode_bytes(output),
+            [Token::Bool(false), Token::Bytes(output)] => Err(Error::Rpc(ErrorDetailRpc {
+                code: 3,
+                message: format!(
+                    "execution reverted: {}@0x{}",
+                    call.function_name,
+                    hex::encode(call.to)
+                ),
+                data: Some(format!("0x{}", hex::encode(output)).into()),
+            })),
+            _ => Err(Error::UnexpectedShape(format!(
+                "aggregate3 result {:?}",
+                fields
+            ))),
+        },
+        other => Err(Error::UnexpectedShape(format!(
+            "aggregate3 result {:?}",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geth::fake;
+    use serde_json::json;
+
+    #[test]
+    fn test_aggregate3_per_call_success() {
+        let geth = Client::build(&[fake::serve(|request| {
+            let input = request["params"][0]["data"].as_str().unwrap();
+            let input = hex::decode(&input[10..]).unwrap(); // skip 0x + selector
+            let aggregate3 = abi().function("aggregate3").unwrap();
+            let Token::Array(call3s) = aggregate3.decode_input(&input).unwrap().remove(0) else {
+                unreachable!()
+            };
+            let results = call3s
+                .into_iter()
+                .map(|call3| {
+                    let Token::Tuple(fields) = call3 else {
+                        unreachable!()
+                    };
+                    match fields[0] {
+                        Token::Address(to) if to == Address::from_low_u64_be(1) => {
+                            Token::Tuple(vec![
+                                Token::Bool(true),
+                                Token::Bytes(ethabi::encode(&[Token::Uint(18.into())])),
+                            ])
+                        }
+                        _ => Token::Tuple(vec![Token::Bool(false), Token::Bytes(vec![])]),
+                    }
+                })
+                .collect();
+            let output = ethabi::encode(&[Token::Array(results)]);
+            json!({ "result": format!("0x{}", hex::encode(output)) })
+        })]);
+        let erc20 = Contract::load(std::fs::File::open("abi/ERC20.json").unwrap()).unwrap();
+        let calls = [1, 2].map(|n| EthCall {
+            to: Address::from_low_u64_be(n),
+            abi: &erc20,
+            function_name: "decimals",
+            function_params: vec![],
+        });
+
+        let results = aggregate3(&geth, &calls, Some(18_000_000)).unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &vec![Token::Uint(18.into())]);
+        assert!(matches!(
+            &results[1],
+            Err(Error::Rpc(ErrorDetailRpc { code: 3, .. }))
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Num, Zero};
+
+// spot price of a uniswap v2 pool from its reserves, decimals applied.
+// exact: reserves and 10^decimals stay big integers, no floats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Price {
+    // block of the reserves the price comes from
+    pub block_number: u32,
+    // token1 per whole token0
+    pub price0: BigRational,
+    // token0 per whole token1
+    pub price1: BigRational,
+}
+
+impl Price {
+    // None while either reserve is empty
+    pub fn from_reserves(
+        block_number: u32,
+        x: &BigInt,
+        y: &BigInt,
+        decimals0: u32,
+        decimals1: u32,
+    ) -> Option<Price> {
+        if x.is_zero() || y.is_zero() {
+            return None;
+        }
+        let ten = BigInt::from(10);
+        let price0 = BigRational::new(y * ten.pow(decimals0), x * ten.pow(decimals1));
+        Some(Price {
+            block_number,
+            price1: price0.recip(),
+            price0,
+        })
+    }
+
+    // a row of uniswap::v2::Pool::price_at
+    pub fn from_row(row: &postgres::Row) -> Option<Price> {
+        let reserve = |column| BigInt::from_str_radix(row.get::<_, &str>(column), 10).ok();
+        Price::from_reserves(
+            row.get::<_, i32>("block_number") as u32,
+            &reserve("x")?,
+            &reserve("y")?,
+            row.get::<_, i32>("decimals0") as u32,
+            row.get::<_, i32>("decimals1") as u32,
+        )
+    }
+}
+
+// ratio as a decimal string, truncated to places digits. 1986.736151
+pub fn decimal(ratio: &BigRational, places: usize) -> String {
+    let scale = BigInt::from(10).pow(places as u32);
+    let scaled = (ratio.numer() * &scale) / ratio.denom();
+    let digits = scaled.to_string();
+    if places == 0 {
+        return digits;
+    }
+    let digits = format!("{:0>width$}", digits, width = places + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    format!("{}.{}", whole, fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usdc_weth_price() {
+        // USDC (6 decimals) / WETH (18 decimals)
+        let x = BigInt::from(33044264430781u64);
+        let y = BigInt::from_str_radix("16632437277688007258761", 10).unwrap();
+        let price = Price::from_reserves(17000000, &x, &y, 6, 18).unwrap();
+        assert_eq!(decimal(&price.price1, 6), "1986.736151");
+        assert_eq!(decimal(&price.price0, 12), "0.000503338100");
+        assert_eq!(
+            price.price1,
+            BigRational::new(
+                BigInt::from_str_radix("33044264430781000000000000", 10).unwrap(),
+                y.clone()
+            )
+        );
+        assert_eq!(
+            &price.price0 * &price.price1,
+            BigRational::from_integer(1.into())
+        );
+        assert!(Price::from_reserves(1, &BigInt::zero(), &y, 6, 18).is_none());
+    }
+
+    #[test]
+    fn test_decimal() {
+        let ratio = BigRational::new(1.into(), 8.into());
+        assert_eq!(decimal(&ratio, 2), "0.12");
+        assert_eq!(decimal(&ratio, 4), "0.1250");
+        assert_eq!(decimal(&ratio, 0), "0");
+        assert_eq!(decimal(&BigRational::from_integer(42.into()), 1), "42.0");
+    }
+}
@@ -0,0 +1,144 @@
+use crate::geth::Client;
+use crate::sql::SqlQuery;
+use sql_query_builder as sql;
+use std::error::Error;
+
+// deeper than any post-merge mainnet reorg; beyond this something else is wrong
+pub const MAX_DEPTH: u32 = 64;
+
+// walk back from block_number until the chain and the db agree on a block hash.
+// db_hash returns the stored hash (no 0x) for a block number, None if not stored.
+pub fn find_common_ancestor<F>(
+    geth: &Client,
+    block_number: u32,
+    mut db_hash: F,
+) -> Result<u32, Box<dyn Error>>
+where
+    F: FnMut(u32) -> Option<String>,
+{
+    let mut number = block_number;
+    loop {
+        match db_hash(number) {
+            Some(hash) => {
+                let block = geth.block(number)?;
+                if block.hash.strip_prefix("0x").unwrap_or(&block.hash) == hash {
+                    return Ok(number);
+                }
+                log::info!(
+                    "reorg: block #{} db hash {} chain hash {}",
+                    number,
+                    hash,
+                    block.hash
+                );
+            }
+            None => return Ok(number),
+        }
+        if block_number - number >= MAX_DEPTH || number == 0 {
+            return Err(Box::from(format!(
+                "reorg: no common ancestor within {} blocks of #{}",
+                MAX_DEPTH, block_number
+            )));
+        }
+        number -= 1;
+    }
+}
+
+// everything ingested for blocks after the ancestor
+pub fn rollback_sql(ancestor: u32) -> Vec<SqlQuery> {
+    [
+        ("logs", "block_number"),
+        ("swaps", "block_number"),
+        ("liquidity_events", "block_number"),
+        ("lp_transfers", "block_number"),
+        ("reserves", "block_number"),
+        ("transactions", "block_number"),
+        ("receipts", "block_number"),
+        ("pools", "created_block_number"),
+        ("blocks", "number"),
+    ]
+    .iter()
+    .map(|(table, column)| {
+        let delete = sql::Delete::new()
+            .delete_from(table)
+            .where_clause(&format!("{} > $1", column));
+        let params: Vec<Box<dyn postgres::types::ToSql + Sync>> = vec![Box::new(ancestor as i32)];
+        (delete.to_string(), params)
+    })
+    .collect()
+}
+
+pub fn rollback(db: &mut crate::sql::Client, ancestor: u32) {
+    let mut db = crate::sql::TransactionClient::new(db);
+    for query in rollback_sql(ancestor) {
+        db.q(query);
+    }
+    db.client.commit().unwrap();
+    log::info!("reorg: rolled back to block #{}", ancestor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geth::fake;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    // serves eth_getBlockByNumber from a number => hash map
+    fn fake_rpc(chain: HashMap<u32, String>) -> String {
+        fake::serve(move |request| {
+            let number_hex = request["params"][0].as_str().unwrap();
+            let number = u32::from_str_radix(number_hex.strip_prefix("0x").unwrap(), 16).unwrap();
+            json!({
+                "result": {
+                    "hash": format!("0x{}", chain[&number]),
+                    "parentHash": format!("0x{}", chain.get(&(number - 1)).cloned().unwrap_or_default()),
+                    "number": number_hex,
+                    "timestamp": "0x0",
+                    "transactions": [],
+                }
+            })
+        })
+    }
+
+    fn chain(blocks: &[(u32, &str)]) -> HashMap<u32, String> {
+        blocks
+            .iter()
+            .map(|(number, hash)| (*number, hash.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_common_ancestor_after_fork() {
+        let canonical = chain(&[(99, "a99"), (100, "a100"), (101, "b101"), (102, "b102")]);
+        let db = chain(&[(99, "a99"), (100, "a100"), (101, "a101"), (102, "a102")]);
+        let geth = Client::build(&[fake_rpc(canonical)]);
+
+        let ancestor = find_common_ancestor(&geth, 102, |n| db.get(&n).cloned()).unwrap();
+        assert_eq!(ancestor, 100);
+    }
+
+    #[test]
+    fn test_common_ancestor_no_fork() {
+        let canonical = chain(&[(99, "a99"), (100, "a100")]);
+        let geth = Client::build(&[fake_rpc(canonical.clone())]);
+
+        let ancestor = find_common_ancestor(&geth, 100, |n| canonical.get(&n).cloned()).unwrap();
+        assert_eq!(ancestor, 100);
+    }
+
+    #[test]
+    fn test_common_ancestor_too_deep() {
+        let canonical = (0..200).map(|n| (n, format!("b{}", n))).collect();
+        let db: HashMap<u32, String> = (0..200).map(|n| (n, format!("a{}", n))).collect();
+        let geth = Client::build(&[fake_rpc(canonical)]);
+
+        assert!(find_common_ancestor(&geth, 199, |n| db.get(&n).cloned()).is_err());
+    }
+
+    #[test]
+    fn test_rollback_sql() {
+        let queries = rollback_sql(100);
+        assert_eq!(queries.len(), 9);
+        assert_eq!(queries[8].0, "DELETE FROM blocks WHERE number > $1");
+    }
+}
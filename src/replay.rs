@@ -0,0 +1,87 @@
+use crate::sql::SqlQuery;
+use sql_query_builder as sql;
+
+// last block a replay of one handler committed, and the --from/--to
+// range it was replaying. handler is a replay --handler name, or "all".
+#[derive(Debug, PartialEq)]
+pub struct Cursor {
+    pub handler: String,
+    pub block_number: u32,
+    pub from_block: Option<u32>,
+    pub to_block: Option<u32>,
+}
+
+impl Cursor {
+    pub fn find(db: &mut crate::sql::Client, handler: &str) -> Option<Cursor> {
+        db.q_last(Self::find_by_handler_sql(handler))
+            .map(|row| Cursor {
+                handler: row.get("handler"),
+                block_number: row.get::<&str, i32>("block_number") as u32,
+                from_block: row.get::<&str, Option<i32>>("from_block").map(|n| n as u32),
+                to_block: row.get::<&str, Option<i32>>("to_block").map(|n| n as u32),
+            })
+    }
+
+    // where a replay of from..=to starts. None when this cursor already
+    // finished that range. a cursor of another range is not resumed.
+    pub fn start(cursor: Option<&Cursor>, from: u32, to: u32) -> Option<u32> {
+        match cursor {
+            Some(cursor) if (cursor.from_block, cursor.to_block) == (Some(from), Some(to)) => {
+                (cursor.block_number < to).then_some(cursor.block_number + 1)
+            }
+            _ => Some(from),
+        }
+    }
+
+    fn find_by_handler_sql(handler: &str) -> SqlQuery {
+        let select = sql::Select::new()
+            .select("*")
+            .from("replay_cursors")
+            .where_clause("handler = $1");
+        (select.to_string(), vec![Box::new(handler.to_owned())])
+    }
+}
+
+impl crate::sql::Ops for Cursor {
+    fn to_upsert_sql(&self) -> SqlQuery {
+        <dyn crate::Ops>::upsert_sql(
+            "replay_cursors",
+            vec!["handler"],
+            vec!["block_number", "from_block", "to_block"],
+            vec![
+                Box::new(self.handler.to_owned()),
+                Box::new(self.block_number as i32),
+                Box::new(self.from_block.map(|n| n as i32)),
+                Box::new(self.to_block.map(|n| n as i32)),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_start() {
+        let cursor = |block_number, from_block, to_block| Cursor {
+            handler: "all".to_string(),
+            block_number,
+            from_block,
+            to_block,
+        };
+        assert_eq!(Cursor::start(None, 1000, 2000), Some(1000));
+        // the same range resumes, or is done
+        let stopped = cursor(1500, Some(1000), Some(2000));
+        assert_eq!(Cursor::start(Some(&stopped), 1000, 2000), Some(1501));
+        let done = cursor(2000, Some(1000), Some(2000));
+        assert_eq!(Cursor::start(Some(&done), 1000, 2000), None);
+        // another range starts over: 500-999 are not skipped
+        assert_eq!(Cursor::start(Some(&done), 500, 3000), Some(500));
+        assert_eq!(Cursor::start(Some(&done), 0, 2000), Some(0));
+        assert_eq!(Cursor::start(Some(&stopped), 1000, 1500), Some(1000));
+        // cursors stored before ranges were
+        let old = cursor(2000, None, None);
+        assert_eq!(Cursor::start(Some(&old), 1000, 2000), Some(1000));
+    }
+}
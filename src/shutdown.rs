@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// SIGINT/SIGTERM ask long running commands to stop once the block or pool
+// transaction they are in has committed. a second signal exits right away,
+// postgres rolls back whatever transaction was open.
+pub fn install() {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            log::warn!("second signal, exiting now");
+            std::process::exit(130);
+        }
+        log::info!("shutdown requested, finishing current work");
+    })
+    .unwrap();
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+// thread::sleep that wakes early on shutdown
+pub fn sleep(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !requested() {
+        let now = Instant::now();
+        if now >= until {
+            break;
+        }
+        std::thread::sleep((until - now).min(Duration::from_millis(100)));
+    }
+}
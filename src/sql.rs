@@ -33,7 +33,7 @@ impl dyn Ops {
             .map(|x| format!("${}", x))
             .collect::<Vec<_>>()
             .join(", ");
-        let conflict = if column_index_names.len() > 0 {
+        let conflict = if !column_index_names.is_empty() {
             format!(
                 "({}) DO UPDATE SET {}",
                 column_index_names.join(", "),
@@ -44,7 +44,7 @@ impl dyn Ops {
                     .join(", ")
             )
         } else {
-            format!("DO NOTHING")
+            "DO NOTHING".to_string()
         };
         let select = sql::Insert::new()
             .insert_into(&format!("{} ({})", table_name, all_names.join(", ")))
@@ -63,7 +63,7 @@ impl TransactionClient<'_> {
     pub fn new(db: &mut Client) -> TransactionClient<'_> {
         let mut transaction = db.client.transaction().unwrap();
         let row = transaction
-            .query_one("select pg_current_xact_id()::text", &vec![])
+            .query_one("select pg_current_xact_id()::text", &[])
             .unwrap();
         let xact_id = row.get::<&str, Option<String>>("pg_current_xact_id");
         TransactionClient {
@@ -74,13 +74,14 @@ impl TransactionClient<'_> {
 
     pub fn q(&mut self, query: SqlQuery) -> Vec<postgres::Row> {
         log::info!(target: "sql", "[xact {}] {} {:?}", self.xact_id, query.0, query.1);
+        crate::metrics::SQL_QUERIES.inc();
         let params: Vec<&(dyn ToSql + Sync)> = query.1.iter().map(|y| &**y).collect();
         self.client.query(&query.0, &params).unwrap()
     }
 
     pub fn first(&mut self, query: SqlQuery) -> Option<postgres::Row> {
         let mut rows = self.q(query);
-        if rows.len() > 0 {
+        if !rows.is_empty() {
             Some(rows.remove(0))
         } else {
             None
@@ -94,18 +95,55 @@ pub struct Client {
 
 pub(crate) fn new() -> Client {
     let config = config::CONFIG.get().unwrap();
+    connect(&config.psql)
+}
 
-    let mut client = postgres::Client::connect(&config.psql, postgres::NoTls).unwrap();
+fn connect(psql: &str) -> Client {
+    let mut client = postgres::Client::connect(psql, postgres::NoTls).unwrap();
 
     log::info!("sql connected");
     embedded::migrations::runner().run(&mut client).unwrap();
-    return Client { client };
+    Client { client }
+}
+
+// database for tests that write sql, from POOLPOLL_TEST_PSQL. those tests
+// are #[ignore]d, `make test-db` runs them. they should only use a
+// TransactionClient and never commit it.
+#[cfg(test)]
+pub fn test_client() -> Client {
+    static MIGRATIONS: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let psql = test_psql();
+    let _running = MIGRATIONS.lock().unwrap();
+    connect(&psql)
+}
+
+#[cfg(test)]
+fn test_psql() -> String {
+    std::env::var("POOLPOLL_TEST_PSQL")
+        .expect("POOLPOLL_TEST_PSQL is not set. run database tests with make test-db")
+}
+
+// for tests of code that commits its own transactions: the migrations in a
+// schema of their own, dropped first if a failed run left it. drop it at
+// the end of the test.
+#[cfg(test)]
+pub fn test_schema_client(schema: &str) -> Client {
+    let psql = test_psql();
+    let mut client = postgres::Client::connect(&psql, postgres::NoTls).unwrap();
+    client
+        .batch_execute(&format!(
+            "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}; SET search_path TO {0}",
+            schema
+        ))
+        .unwrap();
+    embedded::migrations::runner().run(&mut client).unwrap();
+    Client { client }
 }
 
 impl Client {
     pub fn q_last(&mut self, query: SqlQuery) -> Option<postgres::Row> {
         let row = self.q(query);
-        if row.len() > 0 {
+        if !row.is_empty() {
             Some(row.into_iter().last().unwrap())
         } else {
             None
@@ -114,6 +152,7 @@ impl Client {
 
     pub fn q(&mut self, query: SqlQuery) -> Vec<postgres::Row> {
         log::info!(target: "sql", "{} {:?}", query.0, query.1);
+        crate::metrics::SQL_QUERIES.inc();
         let params: Vec<&(dyn ToSql + Sync)> = query.1.iter().map(|y| &**y).collect();
         self.client.query(&query.0, &params).unwrap()
     }
@@ -4,6 +4,8 @@ pub mod v3 {
 
 pub mod v2 {
     use crate::geth::InfuraLog;
+    use crate::geth::{self, CallResult, EthCall};
+    use crate::multicall;
     use crate::{geth::Client, sql::SqlQuery};
     use ethabi::token::Token;
     use ethabi::Contract;
@@ -16,13 +18,22 @@ pub mod v2 {
     use std::error::Error;
     use std::sync::OnceLock;
 
-    const UNISWAP_FACTORY: &str = "5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f";
+    pub const UNISWAP_FACTORY: &str = "5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f";
     pub static ABI: OnceLock<Contract> = OnceLock::new();
     // Swap(address,uint256,uint256,uint256,uint256,address) 	0xd78ad95f
     pub const TOPIC_SWAP: &str =
         "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
     pub const TOPIC_SYNC: &str =
         "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1";
+    // PairCreated(address,address,address,uint256) 	0x0d3648bd, from the factory
+    pub const TOPIC_PAIR_CREATED: &str =
+        "0x0d3648bd0f6ba80134a33ba9275ac585d9d315f0ad8355cddefde31afa28d0e9";
+    // Mint(address,uint256,uint256) 	0x4c209b5f
+    pub const TOPIC_MINT: &str =
+        "0x4c209b5fc8ad50758f13e2e1088ba56a560dff690a1c6fef26394f4c03821c4f";
+    // Burn(address,uint256,uint256,address) 	0xdccd412f
+    pub const TOPIC_BURN: &str =
+        "0xdccd412f0b1252819cb1fd330b93224ca42612892bb3f4f789976e6d81936496";
 
     #[derive(Debug)]
     pub struct AddressStringNox(pub String);
@@ -72,6 +83,12 @@ pub mod v2 {
         pub contract_address: Address,
         pub token0: Address,
         pub token1: Address,
+        // position in the factory's allPairs, None until discover or
+        // PairCreated has seen it
+        pub uniswap_v2_index: Option<u32>,
+        // block and transaction of the pair's PairCreated, when seen
+        pub created_block_number: Option<u32>,
+        pub created_transaction_index: Option<u32>,
     }
 
     #[derive(Debug)]
@@ -87,6 +104,7 @@ pub mod v2 {
         pub pool: &'a Pool,
         pub block_number: u128,
         pub transaction_index: u32,
+        pub log_index: u32,
         pub in0_eth: BigInt,
         pub in1_eth: BigInt,
         pub call_params: SwapCall,
@@ -94,61 +112,249 @@ pub mod v2 {
 
     #[derive(Debug)]
     pub(crate) struct SwapCall {
+        // the caller of swap, usually a router
+        pub sender: String,
+        // who received the out amounts
+        pub to: String,
         pub in0: BigInt,
         pub in1: BigInt,
         pub out0: BigInt,
         pub out1: BigInt,
     }
 
-    impl From<&InfuraLog> for SwapCall {
-        fn from(value: &InfuraLog) -> Self {
-            let in0 = BigInt::from_str_radix(&value.data[2..66], 16).unwrap();
-            let in1 = BigInt::from_str_radix(&value.data[66..130], 16).unwrap();
-            let out0 = BigInt::from_str_radix(&value.data[130..194], 16).unwrap();
-            let out1 = BigInt::from_str_radix(&value.data[194..258], 16).unwrap();
-            SwapCall {
-                in0,
-                in1,
-                out0,
-                out1,
-            }
+    // a Mint or Burn, liquidity added to or removed from a pool
+    #[derive(Debug)]
+    pub(crate) struct LiquidityEvent<'a> {
+        pub pool: &'a Pool,
+        pub block_number: u128,
+        pub transaction_index: u32,
+        pub log_index: u32,
+        // "mint" or "burn"
+        pub kind: &'static str,
+        // the caller of mint/burn, usually the router
+        pub sender: String,
+        // who received the burned amounts, None for mints. a mint's LP
+        // tokens go to the to of its LpTransfer from the zero address.
+        pub to: Option<String>,
+        pub amount0: BigInt,
+        pub amount1: BigInt,
+    }
+
+    // pool (LP) token Transfer. from the zero address on mint, to the pool
+    // itself before a burn.
+    #[derive(Debug)]
+    pub(crate) struct LpTransfer<'a> {
+        pub pool: &'a Pool,
+        pub block_number: u128,
+        pub transaction_index: u32,
+        pub log_index: u32,
+        pub from: String,
+        pub to: String,
+        pub value: BigInt,
+    }
+
+    // nth 32 byte word of a log's data, in hex
+    fn data_word(log: &InfuraLog, n: usize) -> CallResult<&str> {
+        log.data
+            .get(2 + n * 64..2 + (n + 1) * 64)
+            .ok_or_else(|| geth::Error::Decode(format!("log data has no word {}", n)))
+    }
+
+    fn data_uint(log: &InfuraLog, n: usize) -> CallResult<BigInt> {
+        BigInt::from_str_radix(data_word(log, n)?, 16)
+            .map_err(|e| geth::Error::Decode(e.to_string()))
+    }
+
+    impl<'a> LiquidityEvent<'a> {
+        pub fn from_log(pool: &'a Pool, log: &InfuraLog) -> CallResult<Self> {
+            let (kind, to) = match log.topics.first().map(String::as_str) {
+                Some(TOPIC_MINT) => ("mint", None),
+                Some(TOPIC_BURN) => ("burn", Some(log.topic_address(2)?)),
+                _ => return Err(geth::Error::Decode("not a Mint or Burn log".to_string())),
+            };
+            Ok(LiquidityEvent {
+                pool,
+                block_number: log.number()? as u128,
+                transaction_index: log.transaction_index,
+                log_index: log.log_index,
+                kind,
+                sender: log.topic_address(1)?,
+                to,
+                amount0: data_uint(log, 0)?,
+                amount1: data_uint(log, 1)?,
+            })
+        }
+    }
+
+    impl crate::sql::Ops for LiquidityEvent<'_> {
+        fn to_upsert_sql(&self) -> crate::sql::SqlQuery {
+            <dyn crate::Ops>::upsert_sql(
+                "liquidity_events",
+                vec!["block_number", "log_index"],
+                vec![
+                    "pool_contract_address",
+                    "transaction_index",
+                    "kind",
+                    "sender",
+                    "to_address",
+                    "amount0",
+                    "amount1",
+                ],
+                vec![
+                    Box::new(self.block_number as i32),
+                    Box::new(self.log_index as i32),
+                    Box::new(format!("{:x}", self.pool.contract_address)),
+                    Box::new(self.transaction_index as i32),
+                    Box::new(self.kind),
+                    Box::new(self.sender.clone()),
+                    Box::new(self.to.clone()),
+                    Box::new(PgNumeric::new(Some(self.amount0.clone().into()))),
+                    Box::new(PgNumeric::new(Some(self.amount1.clone().into()))),
+                ],
+            )
+        }
+    }
+
+    impl<'a> LpTransfer<'a> {
+        pub fn from_log(pool: &'a Pool, log: &InfuraLog) -> CallResult<Self> {
+            Ok(LpTransfer {
+                pool,
+                block_number: log.number()? as u128,
+                transaction_index: log.transaction_index,
+                log_index: log.log_index,
+                from: log.topic_address(1)?,
+                to: log.topic_address(2)?,
+                value: data_uint(log, 0)?,
+            })
+        }
+    }
+
+    impl crate::sql::Ops for LpTransfer<'_> {
+        fn to_upsert_sql(&self) -> crate::sql::SqlQuery {
+            <dyn crate::Ops>::upsert_sql(
+                "lp_transfers",
+                vec!["block_number", "log_index"],
+                vec![
+                    "pool_contract_address",
+                    "transaction_index",
+                    "from_address",
+                    "to_address",
+                    "value",
+                ],
+                vec![
+                    Box::new(self.block_number as i32),
+                    Box::new(self.log_index as i32),
+                    Box::new(format!("{:x}", self.pool.contract_address)),
+                    Box::new(self.transaction_index as i32),
+                    Box::new(self.from.clone()),
+                    Box::new(self.to.clone()),
+                    Box::new(PgNumeric::new(Some(self.value.clone().into()))),
+                ],
+            )
+        }
+    }
+
+    impl TryFrom<&InfuraLog> for SwapCall {
+        type Error = geth::Error;
+
+        fn try_from(value: &InfuraLog) -> CallResult<Self> {
+            Ok(SwapCall {
+                sender: value.topic_address(1)?,
+                to: value.topic_address(2)?,
+                in0: data_uint(value, 0)?,
+                in1: data_uint(value, 1)?,
+                out0: data_uint(value, 2)?,
+                out1: data_uint(value, 3)?,
+            })
         }
     }
 
     impl Pool {
+        // the pool a factory PairCreated log announces, tokens included
+        pub fn from_pair_created(log: &InfuraLog) -> CallResult<Pool> {
+            let address = |hex: &str| {
+                hex::decode(hex)
+                    .map(|bytes| Address::from_slice(&bytes))
+                    .map_err(|e| geth::Error::Decode(e.to_string()))
+            };
+            // the factory emits allPairs.length after the push
+            let length = data_uint(log, 1)?;
+            let index = u32::try_from(length - 1)
+                .map_err(|e| geth::Error::Decode(format!("pair index: {}", e)))?;
+            Ok(Pool {
+                contract_address: address(&data_word(log, 0)?[24..])?,
+                token0: address(&log.topic_address(1)?)?,
+                token1: address(&log.topic_address(2)?)?,
+                uniswap_v2_index: Some(index),
+                created_block_number: Some(log.number()?),
+                created_transaction_index: Some(log.transaction_index),
+            })
+        }
+
+        // token0 and token1 for many pools through multicall
         pub fn tokens(
             geth: &Client,
             abi: &Contract,
-            address: &Address,
-        ) -> Result<(Address, Address), Box<dyn std::error::Error>> {
-            let result_t0 = geth.eth_call(address, abi, "token0", &vec![], None)?;
-            let Token::Address(addr_t0) = result_t0[0] else {
-                println!("{:?}", result_t0[0]);
-                unreachable!()
+            addresses: &[Address],
+        ) -> CallResult<Vec<CallResult<(Address, Address)>>> {
+            let calls = addresses
+                .iter()
+                .flat_map(|address| {
+                    ["token0", "token1"].map(|function_name| EthCall {
+                        to: *address,
+                        abi,
+                        function_name,
+                        function_params: vec![],
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut results = multicall::aggregate3(geth, &calls, None)?.into_iter();
+            let mut token = |name: &str| {
+                let result = results.next().unwrap()?;
+                let Token::Address(address) = result[0] else {
+                    let e = format!("{} => {:?}", name, result[0]);
+                    return Err(geth::Error::UnexpectedShape(e));
+                };
+                Ok(address)
             };
-            let result_t1 = geth.eth_call(address, abi, "token1", &vec![], None)?;
-            let Token::Address(addr_t1) = result_t1[0] else {
-                println!("{:?}", result_t1[0]);
-                unreachable!()
-            };
-            Ok((addr_t0, addr_t1))
+            Ok(addresses
+                .iter()
+                .map(|_| {
+                    let token0 = token("token0");
+                    let token1 = token("token1");
+                    Ok((token0?, token1?))
+                })
+                .collect())
         }
 
+        // getReserves for many pools through multicall, all at eth_block
         pub fn reserves(
             geth: &Client,
             abi: &Contract,
-            address: &Address,
+            addresses: &[Address],
             eth_block: u32,
-        ) -> Result<(U256, U256), Box<dyn std::error::Error>> {
-            let result = geth.eth_call(address, abi, "getReserves", &vec![], Some(eth_block))?;
-            let Token::Uint(r0) = result[0] else {
-                println!("{:?}", result[0]);
-                unreachable!()
-            };
-            let Token::Uint(r1) = result[1] else {
-                unreachable!()
-            };
-            Ok((r0, r1))
+        ) -> CallResult<Vec<CallResult<(U256, U256)>>> {
+            let calls = addresses
+                .iter()
+                .map(|address| EthCall {
+                    to: *address,
+                    abi,
+                    function_name: "getReserves",
+                    function_params: vec![],
+                })
+                .collect::<Vec<_>>();
+            let results = multicall::aggregate3(geth, &calls, Some(eth_block))?;
+            Ok(results
+                .into_iter()
+                .map(|result| {
+                    let result = result?;
+                    let (Token::Uint(r0), Token::Uint(r1)) = (&result[0], &result[1]) else {
+                        let e = format!("getReserves => {:?}", result);
+                        return Err(geth::Error::UnexpectedShape(e));
+                    };
+                    Ok((*r0, *r1))
+                })
+                .collect())
         }
 
         pub fn all() -> SqlQuery {
@@ -163,6 +369,29 @@ pub mod v2 {
                 .where_clause("contract_address = $1");
             (select.to_string(), vec![Box::new(contract_address)])
         }
+
+        // reserves and token decimals for the pool's price at block_number,
+        // from its last Sync at or before it. rows for price::Price::from_row
+        pub fn price_at(&self, block_number: u32) -> SqlQuery {
+            let select = sql::Select::new()
+                .select("reserves.block_number, reserves.x, reserves.y")
+                .select("coin0.decimals AS decimals0, coin1.decimals AS decimals1")
+                .from("reserves")
+                .inner_join("pools ON pools.contract_address = reserves.contract_address")
+                .inner_join("coins coin0 ON coin0.contract_address = pools.token0")
+                .inner_join("coins coin1 ON coin1.contract_address = pools.token1")
+                .where_clause("reserves.contract_address = $1")
+                .where_clause("reserves.block_number <= $2")
+                .order_by("reserves.block_number desc")
+                .limit("1");
+            (
+                select.to_string(),
+                vec![
+                    Box::new(format!("{:x}", self.contract_address)),
+                    Box::new(block_number as i32),
+                ],
+            )
+        }
     }
 
     impl<'a> Reserves<'a> {
@@ -175,23 +404,28 @@ pub mod v2 {
             }
         }
 
-        pub fn find_by_pool(pool: &Pool) -> SqlQuery {
+        // the pool's latest reserves at or before block_number
+        pub fn find_by_pool_at(pool: &Pool, block_number: u32) -> SqlQuery {
             let select = sql::Select::new()
                 .select("*")
                 .from("reserves")
                 .where_clause("contract_address = $1")
+                .where_clause("block_number <= $2")
                 .order_by("block_number desc")
                 .limit("1");
 
             (
                 select.to_string(),
-                vec![Box::new(format!("{:x}", pool.contract_address))],
+                vec![
+                    Box::new(format!("{:x}", pool.contract_address)),
+                    Box::new(block_number as i32),
+                ],
             )
         }
 
         pub fn from_row(row: &postgres::Row, pool: &'a Pool) -> Self {
             Reserves {
-                pool: pool,
+                pool,
                 x: U256::from_str_radix(row.get::<_, _>("x"), 10).unwrap(),
                 y: U256::from_str_radix(row.get::<_, _>("y"), 10).unwrap(),
                 block_number: row.get::<_, i32>("block_number") as u128,
@@ -215,15 +449,26 @@ pub mod v2 {
         }
     }
 
+    impl Swap<'_> {
+        // swaps rows a replay of the swap handler writes again
+        pub fn delete_by_block_sql(block_number: u32) -> SqlQuery {
+            let delete = sql::Delete::new()
+                .delete_from("swaps")
+                .where_clause("block_number = $1");
+            (delete.to_string(), vec![Box::new(block_number as i32)])
+        }
+    }
+
     impl crate::sql::Ops for Swap<'_> {
         fn to_upsert_sql(&self) -> crate::sql::SqlQuery {
             <dyn crate::Ops>::upsert_sql(
                 "swaps",
-                vec![],
+                vec!["block_number", "log_index"],
                 vec![
                     "pool_contract_address",
-                    "block_number",
                     "transaction_index",
+                    "sender",
+                    "to_address",
                     "in0",
                     "in0_eth",
                     "in1",
@@ -232,9 +477,12 @@ pub mod v2 {
                     "out1",
                 ],
                 vec![
-                    Box::new(format!("{:x}", self.pool.contract_address)),
                     Box::new(self.block_number as i32),
+                    Box::new(self.log_index as i32),
+                    Box::new(format!("{:x}", self.pool.contract_address)),
                     Box::new(self.transaction_index as i32),
+                    Box::new(self.call_params.sender.clone()),
+                    Box::new(self.call_params.to.clone()),
                     Box::new(PgNumeric::new(Some(self.call_params.in0.clone().into()))),
                     Box::new(PgNumeric::new(Some(self.in0_eth.clone().into()))),
                     Box::new(PgNumeric::new(Some(self.call_params.in1.clone().into()))),
@@ -254,22 +502,40 @@ pub mod v2 {
                 ),
                 token0: Address::from_slice(&hex::decode(row.get::<_, String>("token0")).unwrap()),
                 token1: Address::from_slice(&hex::decode(row.get::<_, String>("token1")).unwrap()),
+                uniswap_v2_index: row
+                    .get::<_, Option<i32>>("uniswap_v2_index")
+                    .map(|index| index as u32),
+                created_block_number: row
+                    .get::<_, Option<i32>>("created_block_number")
+                    .map(|number| number as u32),
+                created_transaction_index: row
+                    .get::<_, Option<i32>>("created_transaction_index")
+                    .map(|index| index as u32),
             }
         }
     }
 
     impl crate::sql::Ops for Pool {
         fn to_upsert_sql(&self) -> crate::sql::SqlQuery {
-            <dyn crate::Ops>::upsert_sql(
-                "pools",
-                vec!["contract_address"],
-                vec!["token0", "token1"],
-                vec![
-                    Box::new(format!("{:x}", self.contract_address)),
-                    Box::new(format!("{:x}", self.token0)),
-                    Box::new(format!("{:x}", self.token1)),
-                ],
-            )
+            let mut columns = vec!["token0", "token1"];
+            let mut values: Vec<Box<dyn postgres::types::ToSql + Sync>> = vec![
+                Box::new(format!("{:x}", self.contract_address)),
+                Box::new(format!("{:x}", self.token0)),
+                Box::new(format!("{:x}", self.token1)),
+            ];
+            // a pool created from its first Sync has none of these, keep
+            // any that discover or PairCreated already stored
+            for (column, value) in [
+                ("uniswap_v2_index", self.uniswap_v2_index),
+                ("created_block_number", self.created_block_number),
+                ("created_transaction_index", self.created_transaction_index),
+            ] {
+                if let Some(value) = value {
+                    columns.push(column);
+                    values.push(Box::new(value as i32));
+                }
+            }
+            <dyn crate::Ops>::upsert_sql("pools", vec!["contract_address"], columns, values)
         }
     }
 
@@ -277,57 +543,135 @@ pub mod v2 {
 
     impl Factory {
         pub(crate) fn setup() {
-            let abi_file = std::fs::File::open("abi/uniswap_v2_factory.json").unwrap();
-            let abi_factory = ethabi::Contract::load(abi_file).unwrap();
-            ABI.set(abi_factory).unwrap();
+            ABI.get_or_init(|| {
+                let abi_file = std::fs::File::open("abi/uniswap_v2_factory.json").unwrap();
+                ethabi::Contract::load(abi_file).unwrap()
+            });
         }
 
         pub(crate) fn pool_count(geth: &Client) -> Result<U256, Box<dyn std::error::Error>> {
             let factory = Address::from_slice(&hex::decode(UNISWAP_FACTORY).unwrap());
-            let result = geth.eth_call(
-                &factory,
-                &ABI.get().unwrap(),
-                "allPairsLength",
-                &vec![],
-                None,
-            )?;
+            let result =
+                geth.eth_call(&factory, ABI.get().unwrap(), "allPairsLength", &[], None)?;
             let Token::Uint(count) = result[0] else {
-                println!("{:?}", result[0]);
-                unreachable!()
+                let e = format!("allPairsLength => {:?}", result[0]);
+                return Err(geth::Error::UnexpectedShape(e).into());
             };
-            return Ok(count);
+            Ok(count)
+        }
+
+        // allPairs indexes in range stored in pools, in order
+        pub(crate) fn sql_stored_indexes(
+            sql: &mut crate::sql::Client,
+            range: &std::ops::Range<u64>,
+        ) -> Vec<u64> {
+            let select = sql::Select::new()
+                .select("uniswap_v2_index")
+                .from("pools")
+                .where_clause("uniswap_v2_index >= $1")
+                .where_clause("uniswap_v2_index < $2")
+                .order_by("uniswap_v2_index");
+            let params: Vec<Box<dyn postgres::types::ToSql + Sync>> =
+                vec![Box::new(range.start as i32), Box::new(range.end as i32)];
+            sql.q((select.to_string(), params))
+                .iter()
+                .map(|row| row.get::<_, i32>("uniswap_v2_index") as u64)
+                .collect()
         }
 
-        // pub(crate) fn sql_pool_count(sql: &mut crate::sql::Client) -> u64 {
-        //     let max_pool = sql_query_builder::Select::new()
-        //         .select("max(uniswap_v2_index)")
-        //         .from("pools");
-        //     let sql_pool_count_rows = sql.q((max_pool.to_string(), vec![]));
-        //     match sql_pool_count_rows[0].try_get::<&str, i32>("max") {
-        //         Ok(count) => count as u64,
-        //         Err(_) => 0,
-        //     }
-        // }
-
-        pub(crate) fn pool_addr(
+        pub(crate) fn pool_addrs(
             geth: &Client,
-            pool_id: u64,
-        ) -> Result<Address, Box<dyn std::error::Error>> {
+            pool_ids: &[u64],
+        ) -> Result<Vec<Address>, Box<dyn std::error::Error>> {
             let factory = Address::from_slice(&hex::decode(UNISWAP_FACTORY).unwrap());
-            let result = geth.eth_call(
-                &factory,
-                &ABI.get().unwrap(),
-                "allPairs",
-                &vec![Token::Uint(pool_id.into())],
-                None,
-            )?;
-            let Token::Address(addr) = result[0] else {
-                unreachable!()
-            };
-            return Ok(addr);
+            let calls = pool_ids
+                .iter()
+                .map(|pool_id| EthCall {
+                    to: factory,
+                    abi: ABI.get().unwrap(),
+                    function_name: "allPairs",
+                    function_params: vec![Token::Uint((*pool_id).into())],
+                })
+                .collect::<Vec<_>>();
+            geth.eth_call_batch(&calls, None)?
+                .into_iter()
+                .map(|result| {
+                    let result = result?;
+                    let Token::Address(addr) = result[0] else {
+                        let e = format!("allPairs => {:?}", result[0]);
+                        return Err(geth::Error::UnexpectedShape(e).into());
+                    };
+                    Ok(addr)
+                })
+                .collect()
         }
     }
 
     #[cfg(test)]
-    mod tests {}
+    mod tests {
+        use super::*;
+
+        fn pair_abi() -> Contract {
+            Contract::load(std::fs::File::open("abi/uniswap_v2_pair.json").unwrap()).unwrap()
+        }
+
+        #[test]
+        fn test_topics_match_pair_abi() {
+            let abi = pair_abi();
+            for (event, topic) in [
+                ("Swap", TOPIC_SWAP),
+                ("Sync", TOPIC_SYNC),
+                ("Mint", TOPIC_MINT),
+                ("Burn", TOPIC_BURN),
+            ] {
+                let signature = abi.event(event).unwrap().signature();
+                assert_eq!(format!("0x{:x}", signature), topic, "{}", event);
+            }
+            let factory =
+                Contract::load(std::fs::File::open("abi/uniswap_v2_factory.json").unwrap())
+                    .unwrap();
+            let signature = factory.event("PairCreated").unwrap().signature();
+            assert_eq!(format!("0x{:x}", signature), TOPIC_PAIR_CREATED);
+        }
+
+        #[test]
+        fn test_burn_from_log() {
+            let pool = Pool {
+                contract_address: [1; 20].into(),
+                token0: [0; 20].into(),
+                token1: [0; 20].into(),
+                uniswap_v2_index: None,
+                created_block_number: None,
+                created_transaction_index: None,
+            };
+            let router = "7a250d5630b4cf539739df2c5dacb4c659f2488d";
+            let lp = "00000000000000000000000000000000000000aa";
+            let log = InfuraLog {
+                block_number: "0x10".to_string(),
+                topics: vec![
+                    TOPIC_BURN.to_string(),
+                    format!("0x{:0>64}", router),
+                    format!("0x{:0>64}", lp),
+                ],
+                data: format!("0x{:0>64x}{:0>64x}", 1_000_000u64, 2u64 << 60),
+                log_index: 7,
+                ..Default::default()
+            };
+            let burn = LiquidityEvent::from_log(&pool, &log).unwrap();
+            assert_eq!(
+                (burn.kind, burn.block_number, burn.log_index),
+                ("burn", 16, 7)
+            );
+            assert_eq!(burn.sender, router);
+            assert_eq!(burn.to.as_deref(), Some(lp));
+            assert_eq!(burn.amount0, BigInt::from(1_000_000));
+            assert_eq!(burn.amount1, BigInt::from(2u64 << 60));
+
+            let short = InfuraLog {
+                data: "0x00".to_string(),
+                ..log
+            };
+            assert!(LiquidityEvent::from_log(&pool, &short).is_err());
+        }
+    }
 }